@@ -0,0 +1,114 @@
+use crate::eval::{Lapis, StreamOptions};
+use cpal::traits::{DeviceTrait, HostTrait};
+use std::str::FromStr;
+
+const HELP: &str = "usage: lapis [options] [scripts]
+the scripts are evaluated at startup (instead of init.rs)
+
+options:
+    --no-init           don't evaluate init.rs
+    --out <name>        output device (the first one with <name> in its name)
+    --in <name>         input device
+    --null              use the null output
+    --sr <rate>         sample rate (of the output, and of the input if --in is given)
+    --buffer <size>     buffer size (same)
+    --zoom <factor>     zoom factor
+    -h, --help          print this";
+
+// command line options
+#[derive(Default)]
+pub struct Args {
+    scripts: Vec<String>,
+    no_init: bool,
+    out_device: Option<String>,
+    in_device: Option<String>,
+    null: bool,
+    sr: Option<u32>,
+    buffer: Option<u32>,
+    zoom: Option<f32>,
+}
+
+impl Args {
+    // none if lapis shouldn't start (asked for help, or a bad argument)
+    pub fn parse() -> Option<Self> {
+        let mut args = Args::default();
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--no-init" => args.no_init = true,
+                "--null" => args.null = true,
+                "--out" => args.out_device = Some(value(iter.next(), &arg)?),
+                "--in" => args.in_device = Some(value(iter.next(), &arg)?),
+                "--sr" => args.sr = Some(value(iter.next(), &arg)?),
+                "--buffer" => args.buffer = Some(value(iter.next(), &arg)?),
+                "--zoom" => args.zoom = Some(value(iter.next(), &arg)?),
+                "-h" | "--help" => {
+                    println!("{HELP}");
+                    return None;
+                }
+                _ if arg.starts_with('-') => {
+                    eprintln!("unknown option: {arg}\n\n{HELP}");
+                    return None;
+                }
+                _ => args.scripts.push(arg),
+            }
+        }
+        Some(args)
+    }
+    // the streams to open, so each one is opened once
+    pub fn streams(&self) -> StreamOptions {
+        let asked_in = self.in_device.is_some();
+        StreamOptions {
+            out_device: if self.null { (None, None) } else { self.device(false) },
+            in_device: self.device(true),
+            null: self.null,
+            sr: self.sr,
+            buffer: self.buffer,
+            in_sr: if asked_in { self.sr } else { None },
+            in_buffer: if asked_in { self.buffer } else { None },
+        }
+    }
+    pub fn apply(&self, lapis: &mut Lapis) {
+        if let Some(zoom) = self.zoom {
+            lapis.zoom_factor = zoom;
+        }
+        if !self.scripts.is_empty() {
+            for script in &self.scripts {
+                lapis.eval_file(script);
+            }
+        } else if !self.no_init
+            && let Ok(init) = std::fs::read_to_string("init.rs")
+        {
+            lapis.eval(&init);
+        }
+    }
+    // (host index, device index) of the named device, none for the default
+    fn device(&self, input: bool) -> (Option<usize>, Option<usize>) {
+        let name = if input { &self.in_device } else { &self.out_device };
+        let Some(name) = name else { return (None, None) };
+        for (i, host) in cpal::ALL_HOSTS.iter().enumerate() {
+            let Ok(host) = cpal::host_from_id(*host) else { continue };
+            let names = if input {
+                let devices = host.input_devices();
+                devices.map(|d| d.map(|d| d.name().unwrap_or_default()).collect::<Vec<_>>())
+            } else {
+                let devices = host.output_devices();
+                devices.map(|d| d.map(|d| d.name().unwrap_or_default()).collect::<Vec<_>>())
+            }
+            .unwrap_or_default();
+            if let Some(j) = names.iter().position(|n| n.contains(name.as_str())) {
+                return (Some(i), Some(j));
+            }
+        }
+        eprintln!("no device named {name}, using the default");
+        (None, None)
+    }
+}
+
+fn value<T: FromStr>(arg: Option<String>, option: &str) -> Option<T> {
+    let v = arg.and_then(|a| a.parse().ok());
+    if v.is_none() {
+        eprintln!("missing or invalid value for {option}\n\n{HELP}");
+    }
+    v
+}
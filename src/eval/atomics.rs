@@ -20,6 +20,9 @@ fn call_shared(expr: &ExprCall, lapis: &Lapis) -> Option<Shared> {
         let arg = expr.args.first()?;
         let val = eval_float_f32(arg, lapis)?;
         Some(shared(val))
+    } else if func == "layer_gain" {
+        let name = eval_string(expr.args.first()?, lapis)?;
+        Some(lapis.layers.get(&name)?.gain.clone())
     } else {
         None
     }
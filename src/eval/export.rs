@@ -0,0 +1,399 @@
+use crate::eval::*;
+use syn::visit_mut::{self, VisitMut};
+
+// constructors that only exist in lapis
+const LAPIS_ONLY: [&str; 7] =
+    ["input", "output_loopback", "snoop", "load_net", "select", "fade_select", "f"];
+
+// constructors that take closures (or are otherwise not written the same in rust)
+const UNTRANSLATABLE: [&str; 21] = [
+    "biquad_bank",
+    "envelope",
+    "envelope2",
+    "envelope3",
+    "envelope_in",
+    "lfo",
+    "lfo2",
+    "lfo3",
+    "lfo_in",
+    "fdn",
+    "fdn2",
+    "feedback2",
+    "map",
+    "multitap",
+    "multitap_linear",
+    "oversample",
+    "resample",
+    "resynth",
+    "shape_fn",
+    "update",
+    "var_fn",
+];
+
+// the expression that makes a net, with the variables it uses filled in.
+// none if it uses a net that wasn't made by an expression (or was edited since)
+pub fn resolve_net_expr(expr: &Expr, lapis: &Lapis) -> Option<Expr> {
+    let mut expr = expr.clone();
+    let mut resolve = Resolve { lapis, complete: true, bound: Vec::new() };
+    resolve.visit_expr_mut(&mut expr);
+    if resolve.complete { Some(expr) } else { None }
+}
+
+// a rust function that builds the net `k`, or the reasons it can't be exported
+pub fn export_rust(k: &str, lapis: &Lapis) -> std::result::Result<String, Vec<String>> {
+    let Some(expr) = lapis.net_exprs.get(k) else {
+        return Err(vec![format!("{k} wasn't made by an expression")]);
+    };
+    let shared = |v: &str| lapis.smap.contains_key(v);
+    let wave = |v: &str| lapis.wmap.contains_key(v);
+    rust_function(k, expr.clone(), &shared, &wave)
+}
+
+fn rust_function(
+    k: &str,
+    mut expr: Expr,
+    shared: &dyn Fn(&str) -> bool,
+    wave: &dyn Fn(&str) -> bool,
+) -> std::result::Result<String, Vec<String>> {
+    let mut rustify = Rustify {
+        is_shared: shared,
+        is_wave: wave,
+        shared: Vec::new(),
+        waves: Vec::new(),
+        errors: Vec::new(),
+    };
+    rustify.visit_expr_mut(&mut expr);
+    if !rustify.errors.is_empty() {
+        return Err(rustify.errors);
+    }
+    let mut params: Vec<String> = rustify.shared.iter().map(|s| format!("{s}: &Shared")).collect();
+    params.extend(rustify.waves.iter().map(|w| format!("{w}: &Arc<Wave>")));
+    let arc = if rustify.waves.is_empty() { "" } else { "use std::sync::Arc;" };
+    let mut file: File = parse_str(&format!(
+        "use fundsp::hacker32::*; {arc} pub fn {k}({}) -> Net {{ Net::wrap(Box::new(PATCH)) }}",
+        params.join(", ")
+    ))
+    .map_err(|e| vec![e.to_string()])?;
+    Fill(Some(expr)).visit_file_mut(&mut file);
+    let code = format!("// exported from lapis\n\n{}", prettyplease::unparse(&file));
+    // make sure what's written is rust
+    parse_str::<File>(&code).map_err(|e| vec![format!("the generated code doesn't parse: {e}")])?;
+    Ok(code)
+}
+
+fn paren(expr: Expr) -> Expr {
+    Expr::Paren(ExprParen {
+        attrs: Vec::new(),
+        paren_token: Default::default(),
+        expr: Box::new(expr),
+    })
+}
+
+fn float(v: f64) -> Option<Expr> {
+    let s = if v.is_nan() {
+        "f32::NAN".into()
+    } else if v.is_infinite() {
+        if v > 0. { "f32::INFINITY".into() } else { "f32::NEG_INFINITY".into() }
+    } else {
+        format!("{:?}", v)
+    };
+    parse_str(&s).ok()
+}
+
+struct Resolve<'a> {
+    lapis: &'a Lapis,
+    complete: bool,
+    // closure parameters, which aren't lapis variables
+    bound: Vec<String>,
+}
+
+impl Resolve<'_> {
+    fn value(&mut self, k: &str) -> Option<Expr> {
+        let lapis = self.lapis;
+        if let Some(v) = lapis.fmap.get(k) {
+            float(*v)
+        } else if let Some(e) = lapis.net_exprs.get(k) {
+            Some(paren(e.clone()))
+        } else if lapis.gmap.contains_key(k) {
+            self.complete = false;
+            None
+        } else if let Some(b) = lapis.bmap.get(k) {
+            parse_str(&b.to_string()).ok()
+        } else if let Some(s) = lapis.string_map.get(k) {
+            parse_str(&format!("{:?}", s)).ok()
+        } else if let Some(v) = lapis.vmap.get(k) {
+            let v: Vec<String> = v.iter().map(|x| format!("{:?}", x)).collect();
+            parse_str(&format!("[{}]", v.join(", "))).ok()
+        } else {
+            // shareds and waves stay variables
+            None
+        }
+    }
+}
+
+impl VisitMut for Resolve<'_> {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        if let Expr::Path(path) = expr
+            && path.qself.is_none()
+            && let Some(k) = path.path.get_ident()
+        {
+            let k = k.to_string();
+            if !self.bound.contains(&k)
+                && let Some(v) = self.value(&k)
+            {
+                *expr = v;
+            }
+        } else if let Expr::Closure(closure) = expr {
+            let params: Vec<String> = closure.inputs.iter().filter_map(pat_ident).collect();
+            let n = params.len();
+            self.bound.extend(params);
+            self.visit_expr_mut(&mut closure.body);
+            self.bound.truncate(self.bound.len() - n);
+        } else if let Expr::MethodCall(call) = expr
+            && call.method == "clone"
+            && let Some(k) = nth_path_ident(&call.receiver, 0)
+            && let Some(e) = self.lapis.net_exprs.get(&k)
+        {
+            // a clone of a net is the same expression
+            *expr = paren(e.clone());
+        } else if let Expr::Call(call) = expr {
+            // the function itself isn't a variable
+            for arg in &mut call.args {
+                self.visit_expr_mut(arg);
+            }
+        } else {
+            visit_mut::visit_expr_mut(self, expr);
+        }
+    }
+}
+
+// argument positions that are integers in fundsp
+fn int_args(func: &str) -> &'static [usize] {
+    match func {
+        "Net::new" | "rfft" | "ifft" => &[0, 1],
+        "Net::scalar" | "chorus" | "cymbal" | "snaredrum" | "mls_bits" => &[0],
+        "pebbles" | "kr" => &[1],
+        "wavech" => &[1, 2],
+        "wavech_at" => &[1, 2, 3, 4],
+        _ => &[],
+    }
+}
+
+// an integer literal where fundsp wants an integer (variables were filled in as floats)
+fn int(expr: &mut Expr) {
+    if let Expr::Lit(ExprLit { lit: Lit::Float(f), .. }) = expr
+        && let Ok(v) = f.base10_parse::<f64>()
+        && let Ok(i) = parse_str(&(v as i64).to_string())
+    {
+        *expr = i;
+    }
+}
+
+struct Rustify<'a> {
+    is_shared: &'a dyn Fn(&str) -> bool,
+    is_wave: &'a dyn Fn(&str) -> bool,
+    // variables that become parameters
+    shared: Vec<String>,
+    waves: Vec<String>,
+    // what can't be written in rust
+    errors: Vec<String>,
+}
+
+impl Rustify<'_> {
+    // lapis functions that combine nets are operators in rust
+    fn operator(call: &ExprCall) -> Option<Expr> {
+        let func = nth_path_ident(&call.func, 0)?;
+        let op = match func.as_str() {
+            "pipe" => BinOp::Shr(Default::default()),
+            "branch" => BinOp::BitXor(Default::default()),
+            "bus" => BinOp::BitAnd(Default::default()),
+            "stack" => BinOp::BitOr(Default::default()),
+            "sum" => BinOp::Add(Default::default()),
+            "product" => BinOp::Mul(Default::default()),
+            "thru" => {
+                return Some(Expr::Unary(ExprUnary {
+                    attrs: Vec::new(),
+                    op: UnOp::Not(Default::default()),
+                    expr: Box::new(paren(call.args.first()?.clone())),
+                }));
+            }
+            _ => return None,
+        };
+        // in parens, since it takes the place of a call
+        Some(paren(Expr::Binary(ExprBinary {
+            attrs: Vec::new(),
+            left: Box::new(paren(call.args.first()?.clone())),
+            op,
+            right: Box::new(paren(call.args.get(1)?.clone())),
+        })))
+    }
+    fn call(&mut self, call: &mut ExprCall) {
+        let mut func = nth_path_ident(&call.func, 0).unwrap_or_default();
+        if func == "Net"
+            && let Some(f) = nth_path_ident(&call.func, 1)
+        {
+            func = format!("Net::{f}");
+        }
+        if LAPIS_ONLY.contains(&func.as_str()) {
+            self.errors.push(format!("`{func}` only exists in lapis"));
+        } else if UNTRANSLATABLE.contains(&func.as_str()) {
+            self.errors.push(format!("`{func}` can't be exported"));
+        }
+        let ints = int_args(&func);
+        for (i, arg) in call.args.iter_mut().enumerate() {
+            if ints.contains(&i) {
+                int(arg);
+            } else {
+                self.visit_expr_mut(arg);
+            }
+        }
+        // the loop point is optional in lapis
+        let loop_arg = match func.as_str() {
+            "wavech" => 2,
+            "wavech_at" => 4,
+            _ => return,
+        };
+        if let Some(arg) = call.args.iter_mut().nth(loop_arg) {
+            *arg = Expr::Call(ExprCall {
+                attrs: Vec::new(),
+                func: Box::new(parse_str("Some").unwrap()),
+                paren_token: Default::default(),
+                args: std::iter::once(arg.clone()).collect(),
+            });
+        } else {
+            call.args.push(parse_str("None").unwrap());
+        }
+    }
+}
+
+impl VisitMut for Rustify<'_> {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Path(path) if path.qself.is_none() && path.path.segments.len() == 1 => {
+                let k = path.path.segments[0].ident.to_string();
+                if (self.is_shared)(&k) && !self.shared.contains(&k) {
+                    self.shared.push(k);
+                } else if (self.is_wave)(&k) && !self.waves.contains(&k) {
+                    self.waves.push(k);
+                }
+            }
+            // fundsp takes f32 where lapis accepts integers
+            Expr::Lit(ExprLit { lit: Lit::Int(i), .. }) if i.suffix().is_empty() => {
+                if let Ok(f) = parse_str(&format!("{}.0", i.base10_digits())) {
+                    *expr = f;
+                }
+            }
+            Expr::Call(call) if nth_path_ident(&call.func, 0).as_deref() == Some("t") => {
+                // the time (the closure is written here, so it isn't checked)
+                *expr = parse_str("lfo(|t| t)").unwrap();
+            }
+            Expr::Call(call) => {
+                // `Box::new(x)` is just `x` here
+                if nth_path_ident(&call.func, 0).as_deref() == Some("Box")
+                    && let Some(arg) = call.args.first()
+                {
+                    *expr = arg.clone();
+                    self.visit_expr_mut(expr);
+                } else if let Some(op) = Self::operator(call) {
+                    *expr = op;
+                    self.visit_expr_mut(expr);
+                } else {
+                    self.call(call);
+                }
+            }
+            Expr::MethodCall(call) if call.method == "backend" => {
+                self.errors.push("backends only exist at runtime".into());
+            }
+            Expr::Closure(_) => {
+                self.errors.push("closures can't be exported".into());
+            }
+            _ => visit_mut::visit_expr_mut(self, expr),
+        }
+    }
+}
+
+// an expression written on one line
+pub fn expr_code(expr: &Expr) -> Option<String> {
+    let mut file: File = parse_str("fn f() { PATCH }").ok()?;
+    Fill(Some(expr.clone())).visit_file_mut(&mut file);
+    let code = prettyplease::unparse(&file);
+    let lines: Vec<&str> = code.lines().map(str::trim).collect();
+    // without the `fn f() {` and `}` around it
+    Some(lines.get(1..lines.len().checked_sub(1)?)?.join(" "))
+}
+
+// puts the patch in the function body
+struct Fill(Option<Expr>);
+
+impl VisitMut for Fill {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        if let Expr::Path(path) = expr
+            && path.path.is_ident("PATCH")
+            && let Some(patch) = self.0.take()
+        {
+            *expr = patch;
+        } else {
+            visit_mut::visit_expr_mut(self, expr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn export(code: &str, shared: &[&str]) -> std::result::Result<String, Vec<String>> {
+        let expr = parse_str(code).unwrap();
+        rust_function("patch", expr, &|v: &str| shared.contains(&v), &|v: &str| v == "w")
+    }
+
+    // the exported function's parameters and the expression it wraps
+    fn exported(code: &str, shared: &[&str]) -> (Vec<FnArg>, Expr) {
+        let code = export(code, shared).unwrap();
+        let file: File = parse_str(&code).unwrap();
+        let Some(Item::Fn(f)) = file.items.last() else { panic!("no function in {code}") };
+        let Some(Stmt::Expr(Expr::Call(wrap), None)) = f.block.stmts.first() else {
+            panic!("no Net::wrap in {code}")
+        };
+        let Some(Expr::Call(boxed)) = wrap.args.first() else { panic!("no Box::new in {code}") };
+        (f.sig.inputs.iter().cloned().collect(), boxed.args[0].clone())
+    }
+
+    fn check(code: &str, shared: &[&str], params: &[&str], rust: &str) {
+        let (p, e) = exported(code, shared);
+        let params: Vec<FnArg> = params.iter().map(|p| parse_str(p).unwrap()).collect();
+        assert_eq!(p, params, "parameters of {code}");
+        assert_eq!(e, parse_str::<Expr>(rust).unwrap(), "export of {code}");
+    }
+
+    #[test]
+    fn operators_and_shareds() {
+        check(
+            "pipe(bus(saw_hz(110), saw_hz(111)) | var(&cutoff), lowpass_q(1)) * 0.5",
+            &["cutoff"],
+            &["cutoff: &Shared"],
+            "((((saw_hz(110.0)) & (saw_hz(111.0))) | var(&cutoff)) >> (lowpass_q(1.0))) * 0.5",
+        );
+        check("thru(lowpass_hz(800, 1))", &[], &[], "!(lowpass_hz(800.0, 1.0))");
+    }
+
+    #[test]
+    fn integer_arguments_and_waves() {
+        check("wavech(w, 1.0, 3.0)", &[], &["w: &Arc<Wave>"], "wavech(w, 1, Some(3))");
+        check("wavech(w, 0)", &[], &["w: &Arc<Wave>"], "wavech(w, 0, None)");
+        check("Net::new(0.0, 2.0)", &[], &[], "Net::new(0, 2)");
+    }
+
+    #[test]
+    fn time_and_boxes() {
+        check("t() >> sine()", &[], &[], "lfo(|t| t) >> sine()");
+        check("Box::new(noise()) * 0.5", &[], &[], "noise() * 0.5");
+    }
+
+    #[test]
+    fn untranslatable_nodes_are_errors() {
+        assert!(export("snoop(0) >> pass()", &[]).is_err());
+        assert!(export("fdn(1) >> resynth(2)", &[]).is_err());
+        assert!(export("map(|x| x)", &[]).is_err());
+    }
+}
@@ -22,6 +22,16 @@ pub fn eval_float(expr: &Expr, lapis: &Lapis) -> Option<f64> {
 fn field_float(expr: &ExprField, lapis: &Lapis) -> Option<f64> {
     let base = nth_path_ident(&expr.base, 0)?;
     if let Member::Named(ident) = &expr.member {
+        if base == "out_stream" {
+            let stats = &lapis.out_stats;
+            match ident.to_string().as_str() {
+                "load" => return Some(stats.load.value() as f64),
+                "peak" => return Some(stats.peak.value() as f64),
+                "xruns" => return Some(stats.xruns.load(Ordering::Relaxed) as f64),
+                "clips" => return Some(stats.clips.load(Ordering::Relaxed) as f64),
+                _ => {}
+            }
+        }
         let config = if base == "out_stream" {
             &lapis.out_stream.as_ref()?.0
         } else if base == "in_stream" {
@@ -0,0 +1,173 @@
+use crate::eval::*;
+
+// the nodes and connections of a net in graphviz dot
+pub fn to_dot(net: &Net, names: &HashMap<NodeId, String>) -> String {
+    let ids: Vec<NodeId> = net.ids().copied().collect();
+    let mut dot = String::from("digraph {\n    rankdir=LR;\n    node [shape=box];\n");
+    if net.inputs() > 0 {
+        dot.push_str(&format!("    inputs [label=\"inputs\\n{}\"];\n", net.inputs()));
+    }
+    if net.outputs() > 0 {
+        dot.push_str(&format!("    outputs [label=\"outputs\\n{}\"];\n", net.outputs()));
+    }
+    for (i, id) in ids.iter().enumerate() {
+        let name = names.get(id).map_or("?", |n| n.as_str());
+        let (ins, outs) = (net.inputs_in(*id), net.outputs_in(*id));
+        dot.push_str(&format!(
+            "    n{} [label=\"{}\\n{:?}\\n{} -> {}\"];\n",
+            i, name, id, ins, outs
+        ));
+    }
+    // edges are labeled "source port:sink port"
+    let from = |source: Source| match source {
+        Source::Local(id, chan) => {
+            ids.iter().position(|i| *i == id).map(|i| (format!("n{i}"), chan))
+        }
+        Source::Global(chan) => Some((String::from("inputs"), chan)),
+        Source::Zero => None,
+    };
+    for (i, id) in ids.iter().enumerate() {
+        for chan in 0..net.inputs_in(*id) {
+            if let Some((src, src_chan)) = from(net.source(*id, chan)) {
+                dot.push_str(&format!(
+                    "    {} -> n{} [label=\"{}:{}\"];\n",
+                    src, i, src_chan, chan
+                ));
+            }
+        }
+    }
+    for chan in 0..net.outputs() {
+        if let Some((src, src_chan)) = from(net.output_source(chan)) {
+            dot.push_str(&format!("    {} -> outputs [label=\"{}:{}\"];\n", src, src_chan, chan));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+// the same structure in the text format `load_net` reads, or the nodes that can't
+// be written (the ones that weren't made by an expression)
+pub fn to_text(net: &Net, lapis: &Lapis) -> std::result::Result<String, Vec<String>> {
+    let ids: Vec<NodeId> = net.ids().copied().collect();
+    let mut text = format!("io {} {}\n", net.inputs(), net.outputs());
+    let mut errors = Vec::new();
+    for (i, id) in ids.iter().enumerate() {
+        match lapis.node_calls.get(id).and_then(expr_code) {
+            Some(code) => text.push_str(&format!("n{} = {}\n", i, code)),
+            None => {
+                let name = lapis.node_names.get(id).map_or("?", |n| n.as_str());
+                errors.push(format!("node {} ({}) wasn't made by an expression", i, name));
+            }
+        }
+    }
+    let from = |source: Source| match source {
+        Source::Local(id, chan) => {
+            ids.iter().position(|i| *i == id).map(|i| (format!("n{i}"), chan))
+        }
+        Source::Global(chan) => Some((String::from("in"), chan)),
+        Source::Zero => None,
+    };
+    for (i, id) in ids.iter().enumerate() {
+        for chan in 0..net.inputs_in(*id) {
+            if let Some((src, src_chan)) = from(net.source(*id, chan)) {
+                text.push_str(&format!("{} {} -> n{} {}\n", src, src_chan, i, chan));
+            }
+        }
+    }
+    for chan in 0..net.outputs() {
+        if let Some((src, src_chan)) = from(net.output_source(chan)) {
+            text.push_str(&format!("{} {} -> out {}\n", src, src_chan, chan));
+        }
+    }
+    if errors.is_empty() { Ok(text) } else { Err(errors) }
+}
+
+// build a net from a list of nodes and edges:
+// io 1 2                   (global inputs and outputs, first)
+// lp = lowpass_hz(800, 1)  (a node, made by any net expression)
+// in 0 -> lp 0             (edges. `in` and `out` are the global inputs and outputs)
+// lp 0 -> out 0
+// or the line that couldn't be read
+pub fn load_net(text: &str, lapis: &mut Lapis) -> std::result::Result<Net, String> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(n, l)| (n + 1, l.trim()))
+        .filter(|(_, l)| !l.is_empty() && !l.starts_with("//"));
+    let fail = |n: usize, line: &str| format!("line {}: `{}`", n, line);
+    let Some((n, line)) = lines.next() else {
+        return Err(String::from("there's no `io` line"));
+    };
+    let (ins, outs) = io_line(line).ok_or_else(|| fail(n, line))?;
+    let mut net = Net::new(ins, outs);
+    let mut nodes = HashMap::new();
+    for (n, line) in lines {
+        load_line(line, &mut net, &mut nodes, lapis).ok_or_else(|| fail(n, line))?;
+    }
+    Ok(net)
+}
+
+// "io inputs outputs"
+fn io_line(line: &str) -> Option<(usize, usize)> {
+    let mut io = line.split_whitespace();
+    if io.next()? != "io" {
+        return None;
+    }
+    Some((io.next()?.parse().ok()?, io.next()?.parse().ok()?))
+}
+
+// a node or an edge
+fn load_line<'a>(
+    line: &'a str,
+    net: &mut Net,
+    nodes: &mut HashMap<&'a str, NodeId>,
+    lapis: &mut Lapis,
+) -> Option<()> {
+    if let Some((src, snk)) = line.split_once("->") {
+        let (src, src_chan) = endpoint(src)?;
+        let (snk, snk_chan) = endpoint(snk)?;
+        let source = match src {
+            "in" if src_chan < net.inputs() => Source::Global(src_chan),
+            _ => {
+                let id = *nodes.get(src)?;
+                if src_chan >= net.outputs_in(id) {
+                    return None;
+                }
+                Source::Local(id, src_chan)
+            }
+        };
+        match snk {
+            "out" if snk_chan < net.outputs() => net.set_output_source(snk_chan, source),
+            _ => {
+                let id = *nodes.get(snk)?;
+                if snk_chan >= net.inputs_in(id)
+                    || matches!(source, Source::Local(src_id, _) if src_id == id)
+                {
+                    return None;
+                }
+                net.set_source(id, snk_chan, source);
+            }
+        }
+    } else {
+        let (name, expr) = line.split_once('=')?;
+        let expr = parse_str::<Expr>(expr.trim()).ok()?;
+        let node = eval_net(&expr, lapis)?;
+        let node_name = wrapped_name(&node, lapis);
+        let call = resolve_net_expr(&expr, lapis);
+        let id = net.push(Box::new(node));
+        lapis.node_names.insert(id, node_name);
+        if let Some(call) = call {
+            lapis.node_calls.insert(id, call);
+        }
+        nodes.insert(name.trim(), id);
+    }
+    Some(())
+}
+
+// "name port"
+fn endpoint(s: &str) -> Option<(&str, usize)> {
+    let mut parts = s.split_whitespace();
+    let name = parts.next()?;
+    let port = parts.next()?.parse().ok()?;
+    Some((name, port))
+}
@@ -0,0 +1,169 @@
+use crate::eval::*;
+use midir::{Ignore, MidiInput, MidiInputConnection};
+
+// (channel (1-16), cc number) -> (variable, min, max)
+type CcMap = HashMap<(u8, u8), (Shared, f32, f32)>;
+
+// (on?, channel, note, velocity)
+pub type NoteEvent = (bool, u8, u8, u8);
+
+// (channel, cc, value)
+pub type CcEvent = (u8, u8, u8);
+
+// what the midi callback owns
+struct MidiState {
+    ccs: CcMap,
+    binder: Receiver<((u8, u8), Option<(Shared, f32, f32)>)>,
+    notes: Sender<NoteEvent>,
+    controls: Sender<CcEvent>,
+    ctx: Arc<OnceLock<Context>>,
+}
+
+pub struct Midi {
+    // never read, holding it keeps the port open
+    #[allow(dead_code)]
+    connection: Option<MidiInputConnection<MidiState>>,
+    // kept here too so a new connection gets them
+    ccs: CcMap,
+    binder: Sender<((u8, u8), Option<(Shared, f32, f32)>)>,
+    notes: Receiver<NoteEvent>,
+    // every cc, for the sliders
+    controls: Receiver<CcEvent>,
+    // code evaluated on note on/off. channel 0 is any channel
+    pub note_on: HashMap<u8, String>,
+    pub note_off: HashMap<u8, String>,
+    // to wake up the ui when a note or cc arrives
+    ctx: Arc<OnceLock<Context>>,
+}
+
+impl Midi {
+    pub fn new(ctx: Arc<OnceLock<Context>>) -> Self {
+        // dummy things
+        let (binder, _) = bounded(1);
+        let (_, notes) = bounded(1);
+        let (_, controls) = bounded(1);
+        Midi {
+            connection: None,
+            ccs: HashMap::new(),
+            binder,
+            notes,
+            controls,
+            note_on: HashMap::new(),
+            note_off: HashMap::new(),
+            ctx,
+        }
+    }
+    pub fn list_ports() -> String {
+        let mut list = String::from("\n// midi inputs:\n");
+        if let Ok(input) = MidiInput::new("lapis") {
+            for (i, port) in input.ports().iter().enumerate() {
+                list.push_str(&format!("// {}: {:?}\n", i, input.port_name(port)));
+            }
+        }
+        list
+    }
+    fn state(&mut self) -> MidiState {
+        let (binder, r) = bounded(256);
+        let (s, notes) = bounded(1024);
+        let (c, controls) = bounded(1024);
+        self.binder = binder;
+        self.notes = notes;
+        self.controls = controls;
+        MidiState { ccs: self.ccs.clone(), binder: r, notes: s, controls: c, ctx: self.ctx.clone() }
+    }
+    // connect to the input port at `index` (from `list_ports`)
+    pub fn connect(&mut self, index: usize) -> Option<()> {
+        self.connection = None;
+        let mut input = MidiInput::new("lapis").ok()?;
+        input.ignore(Ignore::All);
+        let port = input.ports().get(index)?.clone();
+        let state = self.state();
+        self.connection = Some(input.connect(&port, "lapis-in", callback, state).ok()?);
+        Some(())
+    }
+    // make a port that other programs can connect to
+    #[cfg(unix)]
+    pub fn connect_virtual(&mut self, name: &str) -> Option<()> {
+        use midir::os::unix::VirtualInput;
+        self.connection = None;
+        let mut input = MidiInput::new("lapis").ok()?;
+        input.ignore(Ignore::All);
+        let state = self.state();
+        self.connection = Some(input.create_virtual(name, callback, state).ok()?);
+        Some(())
+    }
+    pub fn disconnect(&mut self) {
+        self.connection = None;
+    }
+    pub fn bind_cc(&mut self, channel: u8, cc: u8, binding: Option<(Shared, f32, f32)>) {
+        if let Some(b) = &binding {
+            self.ccs.insert((channel, cc), b.clone());
+        } else {
+            self.ccs.remove(&(channel, cc));
+        }
+        let _ = self.binder.try_send(((channel, cc), binding));
+    }
+    pub fn clear(&mut self) {
+        for key in std::mem::take(&mut self.ccs).into_keys() {
+            let _ = self.binder.try_send((key, None));
+        }
+        self.note_on.clear();
+        self.note_off.clear();
+    }
+    // the code to evaluate for the notes that arrived since the last call
+    pub fn note_code(&self) -> Vec<String> {
+        let mut code = Vec::new();
+        while let Ok((on, channel, note, velocity)) = self.notes.try_recv() {
+            let map = if on { &self.note_on } else { &self.note_off };
+            if let Some(c) = map.get(&channel).or(map.get(&0)) {
+                let c = c.replace("@v", &velocity.to_string()).replace("@", &note.to_string());
+                code.push(c);
+            }
+        }
+        code
+    }
+    // the ccs that arrived since the last call
+    pub fn cc_events(&self) -> Vec<CcEvent> {
+        self.controls.try_iter().collect()
+    }
+}
+
+// runs on the midi thread
+fn callback(_: u64, message: &[u8], state: &mut MidiState) {
+    while let Ok((key, binding)) = state.binder.try_recv() {
+        if let Some(b) = binding {
+            state.ccs.insert(key, b);
+        } else {
+            state.ccs.remove(&key);
+        }
+    }
+    let (Some(status), Some(data1)) = (message.first(), message.get(1)) else { return };
+    let data2 = message.get(2).copied().unwrap_or(0);
+    let channel = (status & 0x0f) + 1;
+    match status & 0xf0 {
+        0xb0 => {
+            if let Some((var, min, max)) = state.ccs.get(&(channel, *data1)) {
+                var.set(min + (max - min) * data2 as f32 / 127.);
+            }
+            if state.controls.try_send((channel, *data1, data2)).is_ok()
+                && let Some(ctx) = state.ctx.get()
+            {
+                ctx.request_repaint();
+            }
+        }
+        // a note on with 0 velocity is a note off
+        0x90 if data2 > 0 => {
+            let _ = state.notes.try_send((true, channel, *data1, data2));
+            if let Some(ctx) = state.ctx.get() {
+                ctx.request_repaint();
+            }
+        }
+        0x80 | 0x90 => {
+            let _ = state.notes.try_send((false, channel, *data1, data2));
+            if let Some(ctx) = state.ctx.get() {
+                ctx.request_repaint();
+            }
+        }
+        _ => {}
+    }
+}
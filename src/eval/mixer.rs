@@ -0,0 +1,378 @@
+use crate::eval::*;
+
+pub enum MixerMessage {
+    // (layer id, fade, fade time, unit, quantum in beats (0 for immediate))
+    Set(usize, Fade, f32, Box<dyn AudioUnit>, f64),
+    Add(MixerLayer),
+    Remove(usize),
+}
+
+// the most layers the mixer holds, and the most swaps it keeps waiting.
+// (the lists never grow on the audio thread)
+pub const MAX_LAYERS: usize = 64;
+
+// what the mixer is done with, sent back so it isn't freed on the audio thread.
+// the contents are only held to be dropped
+#[allow(dead_code)]
+pub enum Garbage {
+    Layer(MixerLayer),
+    Unit(Box<dyn AudioUnit>),
+}
+
+// the gui side of a layer
+pub struct Layer {
+    pub id: usize,
+    pub gain: Shared,
+    pub mute: Shared,
+}
+
+// the audio side of a layer. swaps and their crossfades happen here, on the audio
+// thread (so quantized ones land on the right sample), and the replaced units are
+// sent back as garbage
+pub struct MixerLayer {
+    id: usize,
+    unit: Box<dyn AudioUnit>,
+    fading: Option<Fading>,
+    gain: Shared,
+    mute: Shared,
+}
+
+// a unit being faded out
+struct Fading {
+    unit: Box<dyn AudioUnit>,
+    fade: Fade,
+    // in samples
+    length: f64,
+    done: f64,
+}
+
+// (fade in, fade out) gains at a position (0..1) in a crossfade
+fn fade_gains(fade: &Fade, x: f32) -> (f32, f32) {
+    match fade {
+        Fade::Power => {
+            let a = x * std::f32::consts::FRAC_PI_2;
+            (a.sin(), a.cos())
+        }
+        _ => {
+            let s = ((6. * x - 15.) * x + 10.) * x * x * x;
+            (s, 1. - s)
+        }
+    }
+}
+
+impl MixerLayer {
+    pub fn new(id: usize, channels: usize, sr: f64, gain: Shared, mute: Shared) -> Self {
+        let mut net = Net::scalar(channels, 0.);
+        net.set_sample_rate(sr);
+        net.allocate();
+        MixerLayer { id, unit: Box::new(net), fading: None, gain, mute }
+    }
+    // start playing `unit`, fading over `length` samples. returns what's no longer needed
+    fn set(
+        &mut self,
+        fade: Fade,
+        length: f64,
+        unit: Box<dyn AudioUnit>,
+    ) -> Option<Box<dyn AudioUnit>> {
+        let old = std::mem::replace(&mut self.unit, unit);
+        if length < 1. {
+            return Some(old);
+        }
+        // a fade that's still going is cut short
+        let fading = Fading { unit: old, fade, length, done: 0. };
+        self.fading.replace(fading).map(|f| f.unit)
+    }
+    // render the next `size` samples into `output`. returns the faded out unit when its fade ends
+    fn process(
+        &mut self,
+        size: usize,
+        output: &mut BufferVec,
+        scratch: &mut BufferVec,
+    ) -> Option<Box<dyn AudioUnit>> {
+        self.unit.process(size, &BufferRef::empty(), &mut output.buffer_mut());
+        let f = self.fading.as_mut()?;
+        f.unit.process(size, &BufferRef::empty(), &mut scratch.buffer_mut());
+        for ch in 0..self.unit.outputs() {
+            let old = &scratch.channel_f32(ch)[..size];
+            let new = &mut output.channel_f32_mut(ch)[..size];
+            for (i, (s, o)) in new.iter_mut().zip(old).enumerate() {
+                let x = ((f.done + i as f64) / f.length).min(1.) as f32;
+                let (fade_in, fade_out) = fade_gains(&f.fade, x);
+                *s = *s * fade_in + o * fade_out;
+            }
+        }
+        f.done += size as f64;
+        if f.done >= f.length { self.fading.take().map(|f| f.unit) } else { None }
+    }
+}
+
+// a swap waiting for its beat
+struct Pending {
+    beat: f64,
+    id: usize,
+    fade: Fade,
+    fade_time: f32,
+    unit: Box<dyn AudioUnit>,
+}
+
+// the limiter's ceiling (-1db)
+const LIMIT: f32 = 0.891;
+
+pub struct Mixer {
+    layers: Vec<MixerLayer>,
+    receiver: Receiver<MixerMessage>,
+    pending: Vec<Pending>,
+    garbage: Sender<Garbage>,
+    collected: Receiver<Garbage>,
+    channels: usize,
+    sr: f64,
+    protection: Protection,
+    stats: StreamStats,
+    // the output, for the scope and `output_loopback`
+    tap: Arc<Tap>,
+    // dc blocker state for each channel (previous input, previous output)
+    dc: Vec<(f32, f32)>,
+    dc_coef: f32,
+    // limiter envelope, and its release coefficient
+    envelope: f32,
+    release: f32,
+    // beats per minute
+    tempo: Shared,
+    // position of the clock in beats
+    beat: f64,
+    layer_buffer: BufferVec,
+    // the output of units being faded out
+    fade_buffer: BufferVec,
+    block: BufferVec,
+    // length of the current block, and how much of it was read
+    size: usize,
+    index: usize,
+}
+
+impl Mixer {
+    pub fn new(
+        main: MixerLayer,
+        channels: usize,
+        sr: f64,
+        tempo: Shared,
+        protection: Protection,
+        stats: StreamStats,
+        receiver: Receiver<MixerMessage>,
+    ) -> Self {
+        let mut layers = Vec::with_capacity(MAX_LAYERS);
+        layers.push(main);
+        let (garbage, collected) = bounded(256);
+        Mixer {
+            layers,
+            receiver,
+            pending: Vec::with_capacity(MAX_LAYERS),
+            garbage,
+            collected,
+            channels,
+            sr,
+            protection,
+            stats,
+            tap: Arc::new(Tap::new(channels, TAP_FRAMES)),
+            dc: vec![(0., 0.); channels],
+            // ~10hz
+            dc_coef: (1. - std::f64::consts::TAU * 10. / sr) as f32,
+            envelope: 0.,
+            // ~100ms
+            release: (-1. / (0.1 * sr)).exp() as f32,
+            tempo,
+            beat: 0.,
+            layer_buffer: BufferVec::new(channels),
+            fade_buffer: BufferVec::new(channels),
+            block: BufferVec::new(channels),
+            size: 0,
+            index: 0,
+        }
+    }
+    pub fn tap(&self) -> Arc<Tap> {
+        self.tap.clone()
+    }
+    // where removed layers and unused units go
+    pub fn garbage(&self) -> Receiver<Garbage> {
+        self.collected.clone()
+    }
+    fn discard(&self, garbage: Garbage) {
+        // if the gui is that far behind, it's dropped here after all
+        let _ = self.garbage.try_send(garbage);
+    }
+    // handle messages from the gui thread. call this once per callback
+    pub fn update(&mut self) {
+        while let Ok(msg) = self.receiver.try_recv() {
+            match msg {
+                MixerMessage::Set(id, fade, fade_time, unit, quantum) => {
+                    if quantum > 0. {
+                        // the next multiple of the quantum
+                        let beat = (self.beat / quantum).floor() * quantum + quantum;
+                        if self.pending.len() < self.pending.capacity() {
+                            self.pending.push(Pending { beat, id, fade, fade_time, unit });
+                        } else {
+                            self.discard(Garbage::Unit(unit));
+                        }
+                    } else {
+                        self.set(id, fade, fade_time, unit);
+                    }
+                }
+                MixerMessage::Add(layer) => {
+                    if self.layers.len() < self.layers.capacity() {
+                        self.layers.push(layer);
+                    } else {
+                        self.discard(Garbage::Layer(layer));
+                    }
+                }
+                MixerMessage::Remove(id) => {
+                    if let Some(i) = self.layers.iter().position(|l| l.id == id) {
+                        let layer = self.layers.remove(i);
+                        self.discard(Garbage::Layer(layer));
+                    }
+                    // and the swaps that were waiting for it
+                    let mut i = 0;
+                    while i < self.pending.len() {
+                        if self.pending[i].id == id {
+                            let p = self.pending.remove(i);
+                            self.discard(Garbage::Unit(p.unit));
+                        } else {
+                            i += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    fn set(&mut self, id: usize, fade: Fade, fade_time: f32, unit: Box<dyn AudioUnit>) {
+        if let Some(layer) = self.layers.iter_mut().find(|l| l.id == id) {
+            if let Some(old) = layer.set(fade, fade_time as f64 * self.sr, unit) {
+                let _ = self.garbage.try_send(Garbage::Unit(old));
+            }
+        } else {
+            self.discard(Garbage::Unit(unit));
+        }
+    }
+    // render the next block. it's cut short if a pending swap is due inside it
+    // so the swap happens exactly on its beat
+    fn render(&mut self) {
+        let beats_per_sample = self.tempo.value() as f64 / 60. / self.sr;
+        let mut size = MAX_BUFFER_SIZE;
+        let mut i = 0;
+        while i < self.pending.len() {
+            if beats_per_sample <= 0. {
+                break;
+            }
+            let due = ((self.pending[i].beat - self.beat) / beats_per_sample).ceil();
+            if due <= 0. {
+                let p = self.pending.remove(i);
+                self.set(p.id, p.fade, p.fade_time, p.unit);
+            } else {
+                size = size.min(due as usize);
+                i += 1;
+            }
+        }
+        self.block.clear();
+        for layer in &mut self.layers {
+            // muted layers keep running so they don't fall out of time
+            let done = layer.process(size, &mut self.layer_buffer, &mut self.fade_buffer);
+            if let Some(unit) = done {
+                let _ = self.garbage.try_send(Garbage::Unit(unit));
+            }
+            if layer.mute.value() == 0. {
+                // a layer that blows up gets muted (and reported) instead of reaching the output
+                let blown = (0..self.channels).any(|ch| {
+                    self.layer_buffer.channel_f32(ch)[..size].iter().any(|s| !s.is_finite())
+                });
+                if blown {
+                    layer.mute.set(1.);
+                    self.stats.fault_layer.store(layer.id, Ordering::Relaxed);
+                    self.stats.faults.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+                let gain = layer.gain.value();
+                for ch in 0..self.channels {
+                    let input = &self.layer_buffer.channel_f32(ch)[..size];
+                    let output = &mut self.block.channel_f32_mut(ch)[..size];
+                    for (out, s) in output.iter_mut().zip(input) {
+                        *out += s * gain;
+                    }
+                }
+            }
+        }
+        // the sum of finite layers can still overflow. plain loops over each channel
+        // so they get vectorized
+        for ch in 0..self.channels {
+            for s in &mut self.block.channel_f32_mut(ch)[..size] {
+                *s = if s.is_finite() { *s } else { 0. };
+            }
+        }
+        if self.protection.dc_block.load(Ordering::Relaxed) {
+            for (ch, (x1, y1)) in self.dc.iter_mut().enumerate() {
+                for s in &mut self.block.channel_f32_mut(ch)[..size] {
+                    let y = *s - *x1 + self.dc_coef * *y1;
+                    *x1 = *s;
+                    *y1 = y;
+                    *s = y;
+                }
+            }
+        }
+        if self.protection.limiter.load(Ordering::Relaxed) {
+            // instant attack, so nothing gets past the ceiling
+            for i in 0..size {
+                let mut peak: f32 = 0.;
+                for ch in 0..self.channels {
+                    peak = peak.max(self.block.at_f32(ch, i).abs());
+                }
+                self.envelope = if peak > self.envelope {
+                    peak
+                } else {
+                    peak + (self.envelope - peak) * self.release
+                };
+                if self.envelope > LIMIT {
+                    let gain = LIMIT / self.envelope;
+                    for ch in 0..self.channels {
+                        let s = self.block.at_f32(ch, i);
+                        self.block.set_f32(ch, i, s * gain);
+                    }
+                }
+            }
+        }
+        let mut clips = 0;
+        for ch in 0..self.channels {
+            let block = &mut self.block.channel_f32_mut(ch)[..size];
+            clips += block.iter().filter(|s| s.abs() > 1.).count();
+            for s in block {
+                *s = s.clamp(-1., 1.);
+            }
+        }
+        if clips > 0 {
+            self.stats.clips.fetch_add(clips as u32, Ordering::Relaxed);
+        }
+        self.tap.write(&self.block, size);
+        self.beat += size as f64 * beats_per_sample;
+        self.size = size;
+        self.index = 0;
+    }
+    // fill an interleaved output buffer, rendering blocks as needed
+    pub fn fill<T>(&mut self, data: &mut [T])
+    where
+        T: SizedSample + FromSample<f32>,
+    {
+        let channels = self.channels;
+        let frames = data.len() / channels;
+        let mut offset = 0;
+        while offset < frames {
+            if self.index >= self.size {
+                self.render();
+            }
+            let n = (self.size - self.index).min(frames - offset);
+            for ch in 0..channels {
+                let block = &self.block.channel_f32(ch)[self.index..self.index + n];
+                for (i, s) in block.iter().enumerate() {
+                    data[(offset + i) * channels + ch] = T::from_sample(*s);
+                }
+            }
+            self.index += n;
+            offset += n;
+        }
+    }
+}
@@ -3,35 +3,199 @@ use cpal::{
     traits::{DeviceTrait, HostTrait, StreamTrait},
 };
 use crossbeam_channel::{Receiver, Sender, bounded};
-use eframe::egui::{Key, Modifiers};
+use eframe::egui::{Color32, Context, Key, Modifiers};
 use fundsp::hacker::*;
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{
+    Arc, OnceLock,
+    atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+};
 use syn::*;
 
 mod arrays;
 mod atomics;
 mod bools;
+mod export;
 mod floats;
+mod graph_text;
 mod helpers;
 mod ints;
+#[cfg(not(target_arch = "wasm32"))]
+mod midi;
+mod mixer;
+mod namespaces;
 mod nets;
+#[cfg(not(target_arch = "wasm32"))]
+mod osc;
+mod poly;
 mod sequencers;
+#[cfg(not(target_arch = "wasm32"))]
+mod server;
 mod sources;
 mod statements;
 mod strings;
+mod tap;
 mod waves;
 use {
-    arrays::*, atomics::*, bools::*, floats::*, helpers::*, ints::*, nets::*, sequencers::*,
-    sources::*, statements::*, strings::*, waves::*,
+    arrays::*, atomics::*, bools::*, export::*, floats::*, graph_text::*, helpers::*, ints::*,
+    mixer::*, namespaces::*, nets::*, poly::*, sequencers::*, sources::*, statements::*,
+    strings::*, tap::*, waves::*,
 };
+#[cfg(not(target_arch = "wasm32"))]
+use {midi::*, osc::*, server::*};
 
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct SliderSettings {
     pub min: f32,
     pub max: f32,
     pub step_by: f64,
     pub speed: f64,
     pub var: String,
+    pub curve: Curve,
+    // shown after the value
+    pub unit: String,
+    // (channel, cc) that moves this slider
+    pub cc: Option<(u8, u8)>,
+    pub automation: Automation,
+}
+
+// how the position of a slider maps to its value
+#[derive(Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Curve {
+    #[default]
+    Linear,
+    // equal distances are equal ratios (for frequencies)
+    Log,
+    // position to this power (2 or 3 for gains)
+    Power(f32),
+}
+
+// a slider's movements, looped when playing
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct Automation {
+    // (seconds from the start, value)
+    pub points: Vec<(f64, f32)>,
+    pub length: f64,
+    // when the recording started
+    #[serde(skip)]
+    pub recording: Option<f64>,
+    #[serde(skip)]
+    pub playing: bool,
+    // when the loop started (set on the next update after playing is turned on)
+    // shared variables are looped on a timer thread from then on
+    #[serde(skip)]
+    pub start: Option<f64>,
+}
+
+// other controls in the sliders window
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub enum Widget {
+    // a float or shared
+    Knob { var: String, min: f32, max: f32 },
+    // a bool
+    Toggle { var: String },
+    // evaluates code when pressed
+    Button { label: String, code: String },
+    // two shareds
+    Xy { x: String, y: String, min: f32, max: f32 },
+    // the elements of a vector
+    Multi { var: String, min: f32, max: f32 },
+    // a float or shared
+    Slider { var: String, min: f32, max: f32 },
+    Label { text: String },
+}
+
+impl Widget {
+    // what a control is about. declaring it again replaces it
+    fn key(&self) -> String {
+        match self {
+            Widget::Knob { var, .. }
+            | Widget::Toggle { var }
+            | Widget::Multi { var, .. }
+            | Widget::Slider { var, .. } => var.clone(),
+            Widget::Button { label, .. } => label.clone(),
+            Widget::Xy { x, y, .. } => format!("{x} {y}"),
+            Widget::Label { text } => text.clone(),
+        }
+    }
+}
+
+// add a control, or replace the one of the same kind for the same thing
+// (so scripts that run on every start don't pile up restored controls)
+pub fn upsert_widget(widgets: &mut Vec<Widget>, widget: Widget) {
+    let same = |w: &Widget| {
+        std::mem::discriminant(w) == std::mem::discriminant(&widget) && w.key() == widget.key()
+    };
+    if let Some(w) = widgets.iter_mut().find(|w| same(w)) {
+        *w = widget;
+    } else {
+        widgets.push(widget);
+    }
+}
+
+// a window of controls made from code
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Panel {
+    pub name: String,
+    pub open: bool,
+    pub widgets: Vec<Widget>,
+}
+
+pub struct ScopeSettings {
+    // the tap it shows (empty for the output)
+    pub source: String,
+    // wait for a rising edge on the first channel, or run free
+    pub trigger: bool,
+    // time across the scope in ms
+    pub timebase: f32,
+    pub colors: Vec<Color32>,
+}
+
+pub struct SpectrumSettings {
+    // the tap it shows (empty for the output)
+    pub source: String,
+    pub size: usize,
+    // how much of the previous analysis is kept (0 for none)
+    pub averaging: f32,
+    pub peak_hold: bool,
+    pub spectrogram: bool,
+}
+
+// published by the output callback
+#[derive(Clone)]
+pub struct StreamStats {
+    // time spent processing relative to the duration of the buffer (smoothed)
+    pub load: Shared,
+    // slowly decaying peak of the load
+    pub peak: Shared,
+    // callbacks that ran late or took too long (estimated)
+    pub xruns: Arc<AtomicU32>,
+    // samples that were clamped
+    pub clips: Arc<AtomicU32>,
+    // how many times a layer was muted for outputting nan/inf, and the id of the last one
+    pub faults: Arc<AtomicU32>,
+    pub fault_layer: Arc<AtomicUsize>,
+}
+
+impl StreamStats {
+    pub fn new() -> Self {
+        StreamStats {
+            load: shared(0.),
+            peak: shared(0.),
+            xruns: Arc::new(AtomicU32::new(0)),
+            clips: Arc::new(AtomicU32::new(0)),
+            faults: Arc::new(AtomicU32::new(0)),
+            fault_layer: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+// optional processing on the output
+#[derive(Clone)]
+pub struct Protection {
+    pub limiter: Arc<AtomicBool>,
+    pub dc_block: Arc<AtomicBool>,
 }
 
 pub struct Lapis {
@@ -40,67 +204,216 @@ pub struct Lapis {
     pub settings: bool,
     pub sliders_window: bool,
     pub sliders: Vec<SliderSettings>,
+    // the slider waiting for a midi controller to move
+    pub slider_learn: Option<usize>,
+    // the threads looping the automation of shared variables
+    #[cfg(not(target_arch = "wasm32"))]
+    pub slider_players: HashMap<String, Stopper>,
+    pub widgets: Vec<Widget>,
+    pub panels: Vec<Panel>,
     pub about: bool,
     pub fmap: HashMap<String, f64>,
     pub vmap: HashMap<String, Vec<f32>>,
     pub gmap: HashMap<String, Net>,
+    // the expression each net was made from, with the variables it used filled in
+    pub net_exprs: HashMap<String, Expr>,
     pub idmap: HashMap<String, NodeId>,
     pub bmap: HashMap<String, bool>,
     pub smap: HashMap<String, Shared>,
     pub wmap: HashMap<String, Arc<Wave>>,
     pub seqmap: HashMap<String, Sequencer>,
+    pub polymap: HashMap<String, Poly>,
     pub eventmap: HashMap<String, EventId>,
     pub srcmap: HashMap<String, Source>,
+    // the function that made each node (when it was made by a call)
+    pub node_names: HashMap<NodeId, String>,
+    // and the call, with its variables filled in
+    pub node_calls: HashMap<NodeId, Expr>,
+    // nets that are bound to the output through their backend
+    pub live: HashSet<String>,
     pub atomic_table_map: HashMap<String, Arc<AtomicTable>>,
     pub string_map: HashMap<String, String>,
-    pub slot: Slot,
-    pub out_stream: Option<(StreamConfig, Stream)>,
-    pub in_stream: Option<(StreamConfig, Stream)>,
+    pub mixer: Sender<MixerMessage>,
+    // what the mixer is done with, freed here instead of on the audio thread.
+    // natively a timer thread frees it, so it doesn't pile up while the gui is idle
+    #[cfg(not(target_arch = "wasm32"))]
+    pub collector: Option<Stopper>,
+    #[cfg(target_arch = "wasm32")]
+    pub garbage: Receiver<Garbage>,
+    pub layers: HashMap<String, Layer>,
+    pub next_layer_id: usize,
+    pub tempo: Shared,
+    // in beats. 0 means swaps happen immediately
+    pub quantize: f64,
+    pub out_stream: Option<(StreamConfig, AudioStream)>,
+    pub out_stats: StreamStats,
+    // the output of the mixer
+    pub out_tap: Arc<Tap>,
+    // made by `snoop` nodes
+    pub taps: HashMap<String, Arc<Tap>>,
+    pub scope_window: bool,
+    pub scope: ScopeSettings,
+    pub spectrum_window: bool,
+    pub spectrum: SpectrumSettings,
+    pub inspector: bool,
+    pub inspector_filter: String,
+    pub graph_window: bool,
+    // the net shown in the graph window
+    pub graph_net: String,
+    pub protection: Protection,
+    // the fault count we last reported
+    pub faults_seen: u32,
+    pub in_stream: Option<(StreamConfig, AudioStream)>,
     pub receiver: Receiver<(usize, f32)>,
     // (modifiers, key, pressed)
     pub keys: HashMap<(Modifiers, Key, bool), String>,
     pub keys_active: bool,
     pub keys_repeat: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub midi: Midi,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub osc: Osc,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub server: EvalServer,
+    // files being evaluated (innermost last), so includes are relative to them
+    pub files: Vec<std::path::PathBuf>,
+    // namespaces made by `include`
+    pub namespaces: HashSet<String>,
+    // files that are evaluated again when they change, and when they last changed
+    pub watched: HashMap<String, Option<std::time::SystemTime>>,
+    // set once the ui starts. threads use it to wake it up
+    pub ctx: Arc<OnceLock<Context>>,
     pub zoom_factor: f32,
     pub quiet: bool,
 }
 
+// the streams opened at startup. the default devices when not given
+#[derive(Default)]
+pub struct StreamOptions {
+    // (host index, device index)
+    pub out_device: (Option<usize>, Option<usize>),
+    pub in_device: (Option<usize>, Option<usize>),
+    pub null: bool,
+    // for the output
+    pub sr: Option<u32>,
+    pub buffer: Option<u32>,
+    // for the input
+    pub in_sr: Option<u32>,
+    pub in_buffer: Option<u32>,
+}
+
 impl Lapis {
-    pub fn new() -> Self {
+    pub fn with_streams(streams: StreamOptions) -> Self {
         // dummy things
-        let (slot, _) = Slot::new(Box::new(dc(0.)));
+        let (mixer, _) = bounded(1);
+        #[cfg(target_arch = "wasm32")]
+        let (_, garbage) = bounded(1);
         let (_, receiver) = bounded(1);
+        let ctx = Arc::new(OnceLock::new());
         let mut lapis = Lapis {
             buffer: String::new(),
             input: String::new(),
             settings: false,
             sliders_window: false,
             sliders: Vec::new(),
+            slider_learn: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            slider_players: HashMap::new(),
+            widgets: Vec::new(),
+            panels: Vec::new(),
             about: false,
             fmap: HashMap::new(),
             vmap: HashMap::new(),
             gmap: HashMap::new(),
+            net_exprs: HashMap::new(),
             idmap: HashMap::new(),
             bmap: HashMap::new(),
             smap: HashMap::new(),
             wmap: HashMap::new(),
             seqmap: HashMap::new(),
+            polymap: HashMap::new(),
             eventmap: HashMap::new(),
             srcmap: HashMap::new(),
+            node_names: HashMap::new(),
+            node_calls: HashMap::new(),
+            live: HashSet::new(),
             atomic_table_map: HashMap::new(),
             string_map: HashMap::new(),
-            slot,
+            mixer,
+            #[cfg(not(target_arch = "wasm32"))]
+            collector: None,
+            #[cfg(target_arch = "wasm32")]
+            garbage,
+            layers: HashMap::new(),
+            next_layer_id: 0,
+            tempo: shared(120.),
+            quantize: 0.,
             out_stream: None,
+            out_stats: StreamStats::new(),
+            out_tap: Arc::new(Tap::new(0, TAP_FRAMES)),
+            taps: HashMap::new(),
+            scope_window: false,
+            scope: ScopeSettings {
+                source: String::new(),
+                trigger: true,
+                timebase: 20.,
+                colors: Vec::new(),
+            },
+            spectrum_window: false,
+            spectrum: SpectrumSettings {
+                source: String::new(),
+                size: 4096,
+                averaging: 0.7,
+                peak_hold: false,
+                spectrogram: false,
+            },
+            inspector: false,
+            inspector_filter: String::new(),
+            graph_window: false,
+            graph_net: String::new(),
+            protection: Protection {
+                limiter: Arc::new(AtomicBool::new(false)),
+                dc_block: Arc::new(AtomicBool::new(false)),
+            },
+            faults_seen: 0,
             in_stream: None,
             receiver,
             keys: HashMap::new(),
             keys_active: false,
             keys_repeat: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            midi: Midi::new(ctx.clone()),
+            #[cfg(not(target_arch = "wasm32"))]
+            osc: Osc::new(ctx.clone()),
+            #[cfg(not(target_arch = "wasm32"))]
+            server: EvalServer::new(ctx.clone()),
+            files: Vec::new(),
+            namespaces: HashSet::new(),
+            watched: HashMap::new(),
+            ctx,
             zoom_factor: 1.,
             quiet: false,
         };
-        lapis.set_out_device(None, None, None, None, None);
-        lapis.set_in_device(None, None, None, None, None);
+        let StreamOptions { out_device: (h, d), sr, buffer, .. } = streams;
+        #[cfg(not(target_arch = "wasm32"))]
+        if streams.null {
+            lapis.set_null_out(None, sr, buffer);
+        }
+        if lapis.out_stream.is_none() && lapis.set_out_device(h, d, None, sr, buffer).is_none() {
+            // a device that was asked for but didn't open
+            if h.is_some() || d.is_some() || sr.is_some() || buffer.is_some() {
+                lapis.buffer.push_str("\n// couldn't open the output device, using the default");
+                lapis.set_out_device(None, None, None, None, None);
+            }
+        }
+        // so things still run (and can be tested) without a sound card
+        #[cfg(not(target_arch = "wasm32"))]
+        if lapis.out_stream.is_none() {
+            lapis.set_null_out(None, None, None);
+            lapis.buffer.push_str("\n// no output device, using the null output");
+        }
+        let (h, d) = streams.in_device;
+        lapis.set_in_device(h, d, None, streams.in_sr, streams.in_buffer);
         lapis
     }
     pub fn eval(&mut self, input: &str) {
@@ -111,6 +424,7 @@ impl Lapis {
                 Ok(stmt) => {
                     let out = eval_stmt(stmt, self);
                     self.buffer.push_str(&out);
+                    self.finish_eval();
                 }
                 Err(err) => {
                     self.buffer.push_str(&format!("\n// error: {}", err));
@@ -126,6 +440,7 @@ impl Lapis {
                     self.buffer.push_str(&std::mem::take(&mut self.input));
                     let out = eval_stmt(stmt, self);
                     self.buffer.push_str(&out);
+                    self.finish_eval();
                 }
                 Err(err) => {
                     self.buffer.push_str(&format!("\n// error: {}", err));
@@ -136,24 +451,142 @@ impl Lapis {
     pub fn quiet_eval(&mut self, input: &str) {
         if let Ok(stmt) = parse_str::<Stmt>(&format!("{{{}\n}}", input)) {
             eval_stmt(stmt, self);
+            self.finish_eval();
+        }
+    }
+    // evaluate a file without echoing it
+    pub fn eval_file(&mut self, path: &str) {
+        let out = self.run_file(path, None);
+        self.buffer.push_str(&format!("\n// {}:", path));
+        self.buffer.push_str(&out);
+        self.finish_eval();
+    }
+    // evaluate a file and return what it printed. relative paths are relative to
+    // the file that's being evaluated (if any). `namespace` puts its bindings in one
+    pub fn run_file(&mut self, path: &str, namespace: Option<&str>) -> String {
+        let path = self.relative_path(path);
+        let Ok(path) = path.canonicalize() else {
+            return format!("\n// can't find {}", path.display());
+        };
+        if self.files.contains(&path) {
+            return format!("\n// include cycle: {}", path.display());
+        }
+        let code = match std::fs::read_to_string(&path) {
+            Ok(code) => code,
+            Err(err) => return format!("\n// can't read {}: {}", path.display(), err),
+        };
+        match parse_str::<Stmt>(&format!("{{{}\n}}", code)) {
+            Ok(mut stmt) => {
+                if let Some(ns) = namespace {
+                    prefix_bindings(&mut stmt, ns);
+                    self.namespaces.insert(ns.to_string());
+                }
+                self.files.push(path);
+                let out = eval_stmt(stmt, self);
+                self.files.pop();
+                out
+            }
+            Err(err) => format!("\n// error in {}: {}", path.display(), err),
+        }
+    }
+    // a path relative to the file that's being evaluated (if any)
+    pub fn relative_path(&self, path: &str) -> std::path::PathBuf {
+        match self.files.last().and_then(|f| f.parent()) {
+            Some(dir) => dir.join(path),
+            None => std::path::PathBuf::from(path),
+        }
+    }
+    // evaluate the watched files that changed
+    pub fn check_watched(&mut self) {
+        let mut changed = Vec::new();
+        for (path, time) in &mut self.watched {
+            if let Ok(modified) = std::fs::metadata(&*path).and_then(|m| m.modified())
+                && *time != Some(modified)
+            {
+                *time = Some(modified);
+                changed.push(path.clone());
+            }
+        }
+        for path in changed {
+            self.eval_file(&path);
+        }
+    }
+    // after each evaluation
+    fn finish_eval(&mut self) {
+        self.commit_live();
+        self.forget_nodes();
+    }
+    // names and calls are only kept for nodes of nets that are still variables
+    fn forget_nodes(&mut self) {
+        let ids: HashSet<NodeId> = self.gmap.values().flat_map(|g| g.ids().copied()).collect();
+        self.node_names.retain(|id, _| ids.contains(id));
+        self.node_calls.retain(|id, _| ids.contains(id));
+    }
+    pub fn commit_live(&mut self) {
+        for k in &self.live {
+            if let Some(g) = self.gmap.get_mut(k)
+                && g.has_backend()
+            {
+                g.commit();
+            }
         }
     }
+    pub fn record_net(&mut self, k: String, expr: Option<Expr>) {
+        match expr {
+            Some(expr) => self.net_exprs.insert(k, expr),
+            None => self.net_exprs.remove(&k),
+        };
+    }
+    // merge `net` into the live net `k`. nodes that are in both keep their state
+    pub fn merge_live(&mut self, k: &str, net: Net) {
+        let sr = self.out_stream.as_ref().map_or(44100., |s| s.0.sample_rate.0 as f64);
+        let Some(g) = self.gmap.get_mut(k) else { return };
+        if g.inputs() != net.inputs() || g.outputs() != net.outputs() {
+            self.buffer.push_str(&format!(
+                "\n// error: {} is live with {} inputs and {} outputs, the new net has {} and {}",
+                k,
+                g.inputs(),
+                g.outputs(),
+                net.inputs(),
+                net.outputs()
+            ));
+            return;
+        }
+        merge_net(g, net, sr, &mut self.node_calls, &mut self.node_names);
+    }
     pub fn drop(&mut self, k: &str) {
         self.fmap.remove(k);
         self.vmap.remove(k);
         self.gmap.remove(k);
+        self.net_exprs.remove(k);
         self.idmap.remove(k);
         self.bmap.remove(k);
         self.smap.remove(k);
         self.wmap.remove(k);
         self.seqmap.remove(k);
+        self.polymap.remove(k);
         self.eventmap.remove(k);
         self.srcmap.remove(k);
+        self.live.remove(k);
         self.atomic_table_map.remove(k);
         self.string_map.remove(k);
     }
     pub fn clear_sliders(&mut self) {
         self.sliders.clear();
+        self.slider_learn = None;
+        self.widgets.clear();
+        self.panels.clear();
+    }
+    // the panel `name`, made if it doesn't exist
+    pub fn panel(&mut self, name: String) -> &mut Panel {
+        let i = match self.panels.iter().position(|p| p.name == name) {
+            Some(i) => i,
+            None => {
+                self.panels.push(Panel { name, open: true, widgets: Vec::new() });
+                self.panels.len() - 1
+            }
+        };
+        &mut self.panels[i]
     }
     pub fn clear_keys(&mut self) {
         self.keys.clear();
@@ -166,6 +599,8 @@ impl Lapis {
         self.vmap.shrink_to_fit();
         self.gmap.clear();
         self.gmap.shrink_to_fit();
+        self.net_exprs.clear();
+        self.net_exprs.shrink_to_fit();
         self.idmap.clear();
         self.idmap.shrink_to_fit();
         self.bmap.clear();
@@ -176,14 +611,125 @@ impl Lapis {
         self.wmap.shrink_to_fit();
         self.seqmap.clear();
         self.seqmap.shrink_to_fit();
+        self.polymap.clear();
+        self.polymap.shrink_to_fit();
         self.eventmap.clear();
         self.eventmap.shrink_to_fit();
         self.srcmap.clear();
         self.srcmap.shrink_to_fit();
+        self.node_names.clear();
+        self.node_names.shrink_to_fit();
+        self.node_calls.clear();
+        self.node_calls.shrink_to_fit();
+        self.live.clear();
+        self.live.shrink_to_fit();
         self.atomic_table_map.clear();
         self.atomic_table_map.shrink_to_fit();
         self.string_map.clear();
         self.string_map.shrink_to_fit();
+        self.namespaces.clear();
+        self.taps.clear();
+    }
+    pub fn play(&mut self, layer: &str, fade: Fade, fade_time: f32, mut g: Net) {
+        let Some(id) = self.add_layer(layer) else { return };
+        if let Some((config, _)) = &self.out_stream
+            && g.inputs() == 0
+            && g.outputs() == config.channels as usize
+        {
+            g.allocate();
+            g.set_sample_rate(config.sample_rate.0 as f64);
+            let msg = MixerMessage::Set(id, fade, fade_time, Box::new(g), self.quantize);
+            let _ = self.mixer.try_send(msg);
+        }
+    }
+    // returns the id of the layer, creating it if it doesn't exist
+    pub fn add_layer(&mut self, name: &str) -> Option<usize> {
+        if let Some(layer) = self.layers.get(name) {
+            return Some(layer.id);
+        }
+        if self.layers.len() >= MAX_LAYERS {
+            self.buffer.push_str(&format!("\n// error: there can only be {} layers", MAX_LAYERS));
+            return None;
+        }
+        let (config, _) = self.out_stream.as_ref()?;
+        let id = self.next_layer_id + 1;
+        let (gain, mute) = (shared(1.), shared(0.));
+        let channels = config.channels as usize;
+        let sr = config.sample_rate.0 as f64;
+        let layer = MixerLayer::new(id, channels, sr, gain.clone(), mute.clone());
+        self.mixer.try_send(MixerMessage::Add(layer)).ok()?;
+        self.next_layer_id = id;
+        self.layers.insert(name.to_string(), Layer { id, gain, mute });
+        Some(id)
+    }
+    pub fn remove_layer(&mut self, name: &str) {
+        if let Some(layer) = self.layers.remove(name) {
+            let _ = self.mixer.try_send(MixerMessage::Remove(layer.id));
+        }
+    }
+    // apply what came from osc, and send the meters
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn update_osc(&mut self) {
+        for event in self.osc.events() {
+            match event {
+                OscEvent::Shared(name, value) => {
+                    if let Some(s) = self.smap.get(&name) {
+                        s.set(value);
+                    }
+                }
+                OscEvent::Eval(code) => self.quiet_eval(&code),
+            }
+        }
+        self.osc.send_meters(&self.smap, &self.out_stats);
+    }
+    // evaluate what came to the eval server, and reply with what it printed
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn update_server(&mut self) {
+        for (code, reply) in self.server.requests() {
+            let start = self.buffer.len();
+            self.eval(&code);
+            let out = &self.buffer[start..];
+            // without the echoed input
+            let out = out.strip_prefix('\n').and_then(|s| s.strip_prefix(&code)).unwrap_or(out);
+            let _ = reply.try_send(out.trim_start_matches('\n').to_string());
+        }
+    }
+    // the last `n` frames of each channel of a tap (empty name for the output)
+    pub fn read_tap(&self, name: &str, n: usize) -> Vec<Vec<f32>> {
+        let tap = if name.is_empty() { Some(&self.out_tap) } else { self.taps.get(name) };
+        tap.map(|t| t.read(n)).unwrap_or_default()
+    }
+    // the last `n` frames of a tap, and how many frames it had written then
+    pub fn read_tap_counted(&self, name: &str, n: usize) -> (usize, Vec<Vec<f32>>) {
+        let tap = if name.is_empty() { Some(&self.out_tap) } else { self.taps.get(name) };
+        tap.map(|t| t.read_counted(n)).unwrap_or_default()
+    }
+    pub fn tap_written(&self, name: &str) -> usize {
+        let tap = if name.is_empty() { Some(&self.out_tap) } else { self.taps.get(name) };
+        tap.map_or(0, |t| t.written())
+    }
+    pub fn tap_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.taps.keys().cloned().collect();
+        names.sort();
+        names
+    }
+    // free what the mixer sent back
+    #[cfg(target_arch = "wasm32")]
+    pub fn free_garbage(&self) {
+        for garbage in self.garbage.try_iter() {
+            drop(garbage);
+        }
+    }
+    // report layers that got muted by the output protection
+    pub fn check_faults(&mut self) {
+        let faults = self.out_stats.faults.load(Ordering::Relaxed);
+        if faults != self.faults_seen {
+            self.faults_seen = faults;
+            let id = self.out_stats.fault_layer.load(Ordering::Relaxed);
+            if let Some((name, _)) = self.layers.iter().find(|(_, l)| l.id == id) {
+                self.buffer.push_str(&format!("\n// muted layer \"{}\" (it output nan/inf)", name));
+            }
+        }
     }
     pub fn set_out_device(
         &mut self,
@@ -218,24 +764,88 @@ impl Lapis {
         if let Some(channels) = channels {
             config.channels = channels;
         }
-        let mut net = Net::scalar(config.channels as usize, 0.);
-        net.allocate();
-        let (slot, slot_back) = Slot::new(Box::new(net));
+        self.start_output(config, |config, mixer, stats| {
+            let stream = match sample_format {
+                cpal::SampleFormat::F32 => run_out::<f32>(&device, config, mixer, stats),
+                cpal::SampleFormat::I16 => run_out::<i16>(&device, config, mixer, stats),
+                cpal::SampleFormat::U16 => run_out::<u16>(&device, config, mixer, stats),
+                format => {
+                    println!("unsupported sample format: {format}");
+                    None
+                }
+            };
+            Some(AudioStream::Device(stream?))
+        })
+    }
+    // an output that isn't connected to anything. the mixer runs on a timer thread
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_null_out(
+        &mut self,
+        channels: Option<u16>,
+        sr: Option<u32>,
+        buffer: Option<u32>,
+    ) -> Option<()> {
+        let frames = buffer.unwrap_or(512).max(1);
+        let config = StreamConfig {
+            channels: channels.unwrap_or(2).max(1),
+            sample_rate: cpal::SampleRate(sr.unwrap_or(44100).max(1)),
+            buffer_size: cpal::BufferSize::Fixed(frames),
+        };
+        self.start_output(config, |config, mut mixer, stats| {
+            let budget = frames as f64 / config.sample_rate.0 as f64;
+            let mut data = vec![0f32; frames as usize * config.channels as usize];
+            let stream = run_timer(budget, move || {
+                let start = now();
+                mixer.update();
+                mixer.fill(&mut data);
+                update_load(&stats, start, budget, false);
+            });
+            Some(AudioStream::Timer(stream))
+        })
+    }
+    // make a mixer for the given config, and replace the output with what `start` makes of it
+    fn start_output(
+        &mut self,
+        config: StreamConfig,
+        start: impl FnOnce(&StreamConfig, Mixer, StreamStats) -> Option<AudioStream>,
+    ) -> Option<()> {
+        // the main layer (id 0) is what `play` swaps into
+        let channels = config.channels as usize;
+        let sr = config.sample_rate.0 as f64;
+        let (gain, mute) = (shared(1.), shared(0.));
+        let main = MixerLayer::new(0, channels, sr, gain.clone(), mute.clone());
+        let (sender, receiver) = bounded(64);
+        let stats = StreamStats::new();
+        let tempo = self.tempo.clone();
+        let protection = self.protection.clone();
+        let mixer = Mixer::new(main, channels, sr, tempo, protection, stats.clone(), receiver);
 
-        let stream = match sample_format {
-            cpal::SampleFormat::F32 => run_out::<f32>(&device, &config, slot_back),
-            cpal::SampleFormat::I16 => run_out::<i16>(&device, &config, slot_back),
-            cpal::SampleFormat::U16 => run_out::<u16>(&device, &config, slot_back),
-            format => {
-                println!("unsupported sample format: {format}");
-                None
+        let tap = mixer.tap();
+        let garbage = mixer.garbage();
+        let stream = start(&config, mixer, stats.clone())?;
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.collector = Some(run_timer(0.05, move || garbage.try_iter().for_each(drop)));
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.garbage = garbage;
+        }
+        self.mixer = sender;
+        self.out_stats = stats;
+        self.out_tap = tap;
+        self.layers.clear();
+        self.layers.insert(String::from("main"), Layer { id: 0, gain, mute });
+        self.next_layer_id = 0;
+        self.faults_seen = 0;
+        // the backends of live nets were in the old stream. a clone has no backend
+        for k in self.live.drain() {
+            if let Some(g) = self.gmap.get_mut(&k) {
+                *g = g.clone();
             }
-        };
-        if let Some(stream) = stream {
-            self.slot = slot;
-            self.out_stream = Some((config, stream));
         }
-        None
+        self.out_stream = Some((config, stream));
+        Some(())
     }
     pub fn set_in_device(
         &mut self,
@@ -284,32 +894,144 @@ impl Lapis {
             }
         };
         if let Some(stream) = stream {
-            self.in_stream = Some((config, stream));
+            self.in_stream = Some((config, AudioStream::Device(stream)));
             self.receiver = r1;
         }
         None
     }
+    // play a wave file as the input (looping). it's read at its own sample rate
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_in_file(&mut self, path: &str) -> Option<()> {
+        let wave = Wave::load(path).ok()?;
+        if wave.channels() == 0 || wave.is_empty() {
+            return None;
+        }
+        let c = wave.channels();
+        let frames = 512;
+        let config = StreamConfig {
+            channels: c as u16,
+            sample_rate: cpal::SampleRate(wave.sample_rate() as u32),
+            buffer_size: cpal::BufferSize::Fixed(frames as u32),
+        };
+        let (s1, r1) = bounded(4096 * c);
+        let mut i = 0;
+        let stream = run_timer(frames as f64 / wave.sample_rate(), move || {
+            for _ in 0..frames {
+                for channel in 0..c {
+                    let _ = s1.try_send((channel, wave.at(channel, i)));
+                }
+                i = (i + 1) % wave.len();
+            }
+        });
+        self.in_stream = Some((config, AudioStream::Timer(stream)));
+        self.receiver = r1;
+        None
+    }
+}
+
+// what keeps a stream running. dropping it stops the stream
+#[allow(dead_code)]
+pub enum AudioStream {
+    Device(Stream),
+    #[cfg(not(target_arch = "wasm32"))]
+    Timer(Stopper),
+}
+
+// tells a thread to stop when it's dropped. the thread checks the flag
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Stopper {
+    stop: Arc<AtomicBool>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Stopper {
+    pub fn new() -> (Self, Arc<AtomicBool>) {
+        let stop = Arc::new(AtomicBool::new(false));
+        (Stopper { stop: stop.clone() }, stop)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for Stopper {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
 }
 
-fn run_out<T>(device: &cpal::Device, config: &StreamConfig, slot: SlotBackend) -> Option<Stream>
+// call `f` every `period` seconds on a new thread
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_timer(period: f64, mut f: impl FnMut() + Send + 'static) -> Stopper {
+    let (stopper, stopped) = Stopper::new();
+    let period = std::time::Duration::from_secs_f64(period);
+    std::thread::spawn(move || {
+        let mut next = std::time::Instant::now();
+        while !stopped.load(Ordering::Relaxed) {
+            f();
+            next += period;
+            let now = std::time::Instant::now();
+            if next > now {
+                std::thread::sleep(next - now);
+            } else {
+                // fell behind, don't try to catch up
+                next = now;
+            }
+        }
+    });
+    stopper
+}
+
+// std::time::Instant panics on wasm
+fn now() -> Option<std::time::Instant> {
+    if cfg!(target_arch = "wasm32") { None } else { Some(std::time::Instant::now()) }
+}
+
+// publish how much of the `budget` (in seconds) was spent since `start`.
+// a callback that was called `late` or went over budget is one xrun
+fn update_load(stats: &StreamStats, start: Option<std::time::Instant>, budget: f64, late: bool) {
+    let load = match start {
+        Some(start) if budget > 0. => Some((start.elapsed().as_secs_f64() / budget) as f32),
+        _ => None,
+    };
+    if late || load.is_some_and(|load| load > 1.) {
+        stats.xruns.fetch_add(1, Ordering::Relaxed);
+    }
+    if let Some(load) = load {
+        stats.load.set(stats.load.value() * 0.9 + load * 0.1);
+        stats.peak.set(load.max(stats.peak.value() * 0.995));
+    }
+}
+
+fn run_out<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    mut mixer: Mixer,
+    stats: StreamStats,
+) -> Option<Stream>
 where
     T: SizedSample + FromSample<f32>,
 {
-    let mut slot = BlockRateAdapter::new(Box::new(slot));
     let channels = config.channels as usize;
-    let mut out = vec![0.; channels];
+    let sr = config.sample_rate.0 as f64;
+    // (callback instant, duration of its buffer)
+    let mut last: Option<(cpal::StreamInstant, f64)> = None;
 
     let err_fn = |err| eprintln!("an error occurred on stream: {err}");
     let stream = device.build_output_stream(
         config,
-        move |data: &mut [T], _| {
-            for frame in data.chunks_mut(channels) {
-                slot.tick(&[], &mut out);
-                for i in 0..channels {
-                    let tmp = if out[i].is_normal() { out[i].clamp(-1., 1.) } else { 0. };
-                    frame[i] = T::from_sample(tmp);
-                }
-            }
+        move |data: &mut [T], info: &cpal::OutputCallbackInfo| {
+            let start = now();
+            let callback = info.timestamp().callback;
+            let budget = (data.len() / channels) as f64 / sr;
+            // the previous buffer ran out well before we got called again
+            let late = last.is_some_and(|(instant, duration)| {
+                callback
+                    .duration_since(&instant)
+                    .is_some_and(|gap| gap.as_secs_f64() > duration * 1.5 + 0.001)
+            });
+            last = Some((callback, budget));
+            mixer.update();
+            mixer.fill(data);
+            update_load(&stats, start, budget, late);
         },
         err_fn,
         None,
@@ -0,0 +1,93 @@
+use crate::eval::*;
+use syn::visit_mut::{self, VisitMut};
+
+// `name` in the namespace `ns` is stored as `ns__name`
+pub fn namespaced(ns: &str, name: &str) -> String {
+    format!("{}__{}", ns, name)
+}
+
+// rewrite `ns::name` paths (where `ns` is a known namespace) to what they're stored as
+pub fn qualify_paths(stmt: &mut Stmt, namespaces: &HashSet<String>) {
+    Qualify(namespaces).visit_stmt_mut(stmt);
+}
+
+// put everything the code binds with `let` in the namespace `ns`, and rename its uses
+pub fn prefix_bindings(stmt: &mut Stmt, ns: &str) {
+    let mut bindings = Bindings(HashSet::new());
+    bindings.visit_stmt_mut(stmt);
+    Prefix { ns, names: bindings.0 }.visit_stmt_mut(stmt);
+}
+
+struct Qualify<'a>(&'a HashSet<String>);
+
+impl VisitMut for Qualify<'_> {
+    fn visit_expr_path_mut(&mut self, expr: &mut ExprPath) {
+        let segments = &expr.path.segments;
+        if expr.qself.is_none()
+            && segments.len() == 2
+            && segments.iter().all(|s| s.arguments.is_none())
+            && self.0.contains(&segments[0].ident.to_string())
+        {
+            let name = namespaced(&segments[0].ident.to_string(), &segments[1].ident.to_string());
+            let span = segments[1].ident.span();
+            expr.path = Path::from(Ident::new(&name, span));
+        }
+    }
+}
+
+struct Bindings(HashSet<String>);
+
+impl Bindings {
+    fn collect(&mut self, pat: &Pat) {
+        match pat {
+            Pat::Ident(pat) => {
+                self.0.insert(pat.ident.to_string());
+            }
+            Pat::Tuple(pat) => pat.elems.iter().for_each(|p| self.collect(p)),
+            Pat::Type(pat) => self.collect(&pat.pat),
+            _ => {}
+        }
+    }
+}
+
+impl VisitMut for Bindings {
+    fn visit_local_mut(&mut self, local: &mut Local) {
+        self.collect(&local.pat);
+        visit_mut::visit_local_mut(self, local);
+    }
+}
+
+struct Prefix<'a> {
+    ns: &'a str,
+    names: HashSet<String>,
+}
+
+impl Prefix<'_> {
+    fn rename(&self, ident: &mut Ident) {
+        let name = ident.to_string();
+        if self.names.contains(&name) {
+            *ident = Ident::new(&namespaced(self.ns, &name), ident.span());
+        }
+    }
+}
+
+impl VisitMut for Prefix<'_> {
+    fn visit_pat_ident_mut(&mut self, pat: &mut PatIdent) {
+        self.rename(&mut pat.ident);
+    }
+    fn visit_expr_path_mut(&mut self, expr: &mut ExprPath) {
+        if expr.qself.is_none()
+            && expr.path.segments.len() == 1
+            && let Some(segment) = expr.path.segments.first_mut()
+            && segment.arguments.is_none()
+        {
+            self.rename(&mut segment.ident);
+        }
+    }
+    // a function named like a variable is still that function
+    fn visit_expr_call_mut(&mut self, call: &mut ExprCall) {
+        for arg in &mut call.args {
+            self.visit_expr_mut(arg);
+        }
+    }
+}
@@ -3,7 +3,7 @@ use fundsp::sound::*;
 
 pub fn eval_net(expr: &Expr, lapis: &mut Lapis) -> Option<Net> {
     match expr {
-        Expr::Call(expr) => call_net(expr, lapis),
+        Expr::Call(expr) => name_node(call_net(expr, lapis), expr, lapis),
         Expr::Binary(expr) => bin_expr_net(expr, lapis),
         Expr::Paren(expr) => eval_net(&expr.expr, lapis),
         Expr::Path(expr) => path_net(&expr.path, lapis),
@@ -15,7 +15,7 @@ pub fn eval_net(expr: &Expr, lapis: &mut Lapis) -> Option<Net> {
 
 pub fn eval_net_cloned(expr: &Expr, lapis: &mut Lapis) -> Option<Net> {
     match expr {
-        Expr::Call(expr) => call_net(expr, lapis),
+        Expr::Call(expr) => name_node(call_net(expr, lapis), expr, lapis),
         Expr::Binary(expr) => bin_expr_net(expr, lapis),
         Expr::Paren(expr) => eval_net(&expr.expr, lapis),
         Expr::Path(expr) => path_net_cloned(&expr.path, lapis),
@@ -25,6 +25,42 @@ pub fn eval_net_cloned(expr: &Expr, lapis: &mut Lapis) -> Option<Net> {
     }
 }
 
+// remember which function made a single node net (for the graph window),
+// and the call itself with its variables filled in (to match it when a live net is redefined)
+fn name_node(net: Option<Net>, expr: &ExprCall, lapis: &mut Lapis) -> Option<Net> {
+    let net = net?;
+    if net.size() == 1
+        && let Some(id) = net.ids().next().copied()
+        && let Some(name) = nth_path_ident(&expr.func, 0)
+        && name != "Box"
+    {
+        lapis.node_names.entry(id).or_insert(name);
+        if !lapis.node_calls.contains_key(&id)
+            && let Some(call) = resolve_net_expr(&Expr::Call(expr.clone()), lapis)
+        {
+            lapis.node_calls.insert(id, call);
+        }
+    }
+    Some(net)
+}
+
+// remember what made a node that was added to a net (so `to_text` can write it)
+fn node_call(id: NodeId, arg: &Expr, lapis: &mut Lapis) {
+    match resolve_net_expr(arg, lapis) {
+        Some(call) => lapis.node_calls.insert(id, call),
+        None => lapis.node_calls.remove(&id),
+    };
+}
+
+// a net that's added to another as a single node keeps the name of what it wraps
+pub fn wrapped_name(unit: &Net, lapis: &Lapis) -> String {
+    let name = match unit.ids().next() {
+        Some(inner) if unit.size() == 1 => lapis.node_names.get(inner).cloned(),
+        _ => None,
+    };
+    name.unwrap_or_else(|| "net".into())
+}
+
 fn method_net(expr: &ExprMethodCall, lapis: &mut Lapis) -> Option<Net> {
     match expr.method.to_string().as_str() {
         "backend" => {
@@ -37,6 +73,11 @@ fn method_net(expr: &ExprMethodCall, lapis: &mut Lapis) -> Option<Net> {
                 && !g.has_backend()
             {
                 return Some(Net::wrap(Box::new(g.backend())));
+            } else if let Some(poly) = lapis.polymap.get_mut(&k)
+                && !poly.has_backend()
+            {
+                let sr = lapis.out_stream.as_ref().map_or(44100., |(c, _)| c.sample_rate.0 as f64);
+                return Some(Net::wrap(Box::new(poly.backend(sr))));
             }
             None
         }
@@ -49,7 +90,12 @@ fn method_net(expr: &ExprMethodCall, lapis: &mut Lapis) -> Option<Net> {
             let id = eval_nodeid(arg, lapis)?;
             let k = nth_path_ident(&expr.receiver, 0)?;
             let net = lapis.gmap.get_mut(&k)?;
-            if net.contains(id) { Some(Net::wrap(net.remove(id))) } else { None }
+            if net.contains(id) {
+                let unit = net.remove(id);
+                lapis.net_exprs.remove(&k);
+                return Some(Net::wrap(unit));
+            }
+            None
         }
         "remove_link" => {
             let arg = expr.args.first()?;
@@ -57,7 +103,9 @@ fn method_net(expr: &ExprMethodCall, lapis: &mut Lapis) -> Option<Net> {
             let k = nth_path_ident(&expr.receiver, 0)?;
             let net = lapis.gmap.get_mut(&k)?;
             if net.contains(id) && net.inputs_in(id) == net.outputs_in(id) {
-                return Some(Net::wrap(net.remove_link(id)));
+                let unit = net.remove_link(id);
+                lapis.net_exprs.remove(&k);
+                return Some(Net::wrap(unit));
             }
             None
         }
@@ -72,7 +120,13 @@ fn method_net(expr: &ExprMethodCall, lapis: &mut Lapis) -> Option<Net> {
                 && unit.inputs() == net.inputs_in(id)
                 && unit.outputs() == net.outputs_in(id)
             {
-                return Some(Net::wrap(net.replace(id, Box::new(unit))));
+                let name = wrapped_name(&unit, lapis);
+                let net = lapis.gmap.get_mut(&k)?;
+                let old = net.replace(id, Box::new(unit));
+                lapis.node_names.insert(id, name);
+                node_call(id, arg1, lapis);
+                lapis.net_exprs.remove(&k);
+                return Some(Net::wrap(old));
             }
             None
         }
@@ -146,7 +200,8 @@ fn unary_net(expr: &ExprUnary, lapis: &mut Lapis) -> Option<Net> {
 
 fn path_net(expr: &Path, lapis: &mut Lapis) -> Option<Net> {
     let k = expr.segments.first()?.ident.to_string();
-    lapis.gmap.remove(&k)
+    // live nets stay where they are (their frontend is connected to the output)
+    if lapis.live.contains(&k) { lapis.gmap.get(&k).cloned() } else { lapis.gmap.remove(&k) }
 }
 
 fn path_net_cloned(expr: &Path, lapis: &Lapis) -> Option<Net> {
@@ -154,8 +209,120 @@ fn path_net_cloned(expr: &Path, lapis: &Lapis) -> Option<Net> {
     lapis.gmap.get(&k).cloned()
 }
 
+// where an input of a node comes from, without the ids (they differ between nets)
+#[derive(PartialEq)]
+enum Wire<'a> {
+    // the call that made the source node, and its output
+    Node(Option<&'a Expr>, usize),
+    Input(usize),
+    Zero,
+}
+
+fn wiring<'a>(net: &Net, id: NodeId, calls: &'a HashMap<NodeId, Expr>) -> Vec<Wire<'a>> {
+    (0..net.inputs_in(id))
+        .map(|i| match net.source(id, i) {
+            Source::Local(src, chan) => Wire::Node(calls.get(&src), chan),
+            Source::Global(chan) => Wire::Input(chan),
+            Source::Zero => Wire::Zero,
+        })
+        .collect()
+}
+
+// make `old` match `new`, keeping the units of nodes that are in both so they don't lose
+// their state, then commit the changes to the backend. a node is in both if it has the same
+// id, or if it was made by the same call (with the same values) and is wired the same way
+pub fn merge_net(
+    old: &mut Net,
+    mut new: Net,
+    sr: f64,
+    calls: &mut HashMap<NodeId, Expr>,
+    names: &mut HashMap<NodeId, String>,
+) -> Option<()> {
+    if old.inputs() != new.inputs() || old.outputs() != new.outputs() {
+        return None;
+    }
+    new.set_sample_rate(sr);
+    new.allocate();
+    // note the wiring of the new net before taking it apart
+    let ids: Vec<NodeId> = new.ids().copied().collect();
+    let mut sources = Vec::new();
+    for id in &ids {
+        let s: Vec<Source> = (0..new.inputs_in(*id)).map(|i| new.source(*id, i)).collect();
+        sources.push(s);
+    }
+    let outputs: Vec<Source> = (0..new.outputs()).map(|i| new.output_source(i)).collect();
+    // new ids -> ids in the old net
+    let mut map = HashMap::new();
+    for id in &ids {
+        if old.contains(*id) {
+            map.insert(*id, *id);
+        }
+    }
+    let old_ids: Vec<NodeId> = old.ids().copied().collect();
+    for id in &ids {
+        if map.contains_key(id) {
+            continue;
+        }
+        let Some(call) = calls.get(id) else { continue };
+        let size = (new.inputs_in(*id), new.outputs_in(*id));
+        let taken: HashSet<NodeId> = map.values().copied().collect();
+        let candidates: Vec<NodeId> = old_ids
+            .iter()
+            .filter(|o| !taken.contains(*o) && calls.get(*o) == Some(call))
+            .filter(|o| (old.inputs_in(**o), old.outputs_in(**o)) == size)
+            .copied()
+            .collect();
+        // prefer one that's wired the same, otherwise the first in order
+        let wires = wiring(&new, *id, calls);
+        let found = candidates.iter().find(|o| wiring(old, **o, calls) == wires);
+        if let Some(o) = found.or(candidates.first()) {
+            map.insert(*id, *o);
+        }
+    }
+    let kept: HashSet<NodeId> = map.values().copied().collect();
+    for id in old_ids {
+        if !kept.contains(&id) {
+            old.remove(id);
+        }
+    }
+    for id in &ids {
+        if !map.contains_key(id) {
+            let unit = new.remove(*id);
+            let pushed = old.push(unit);
+            if let Some(call) = calls.get(id).cloned() {
+                calls.insert(pushed, call);
+            }
+            if let Some(name) = names.get(id).cloned() {
+                names.insert(pushed, name);
+            }
+            map.insert(*id, pushed);
+        }
+    }
+    let map_source = |s: Source| match s {
+        Source::Local(id, chan) => Source::Local(map[&id], chan),
+        s => s,
+    };
+    for (id, s) in ids.iter().zip(sources) {
+        for (chan, source) in s.into_iter().enumerate() {
+            old.set_source(map[id], chan, map_source(source));
+        }
+    }
+    for (chan, source) in outputs.into_iter().enumerate() {
+        old.set_output_source(chan, map_source(source));
+    }
+    old.commit();
+    Some(())
+}
+
 pub fn net_methods(expr: &ExprMethodCall, lapis: &mut Lapis) -> Option<()> {
-    match expr.method.to_string().as_str() {
+    let method = expr.method.to_string();
+    // an edited net isn't what its expression makes anymore
+    if !matches!(method.as_str(), "to_dot" | "to_text" | "commit" | "set_sample_rate" | "reset")
+        && let Some(k) = nth_path_ident(&expr.receiver, 0)
+    {
+        lapis.net_exprs.remove(&k);
+    }
+    match method.as_str() {
         "remove" => {
             let arg = expr.args.first()?;
             let id = eval_nodeid(arg, lapis)?;
@@ -185,7 +352,30 @@ pub fn net_methods(expr: &ExprMethodCall, lapis: &mut Lapis) -> Option<()> {
                 && unit.inputs() == net.inputs_in(id)
                 && unit.outputs() == net.outputs_in(id)
             {
+                let name = wrapped_name(&unit, lapis);
+                let net = lapis.gmap.get_mut(&k)?;
                 net.replace(id, Box::new(unit));
+                lapis.node_names.insert(id, name);
+                node_call(id, arg1, lapis);
+            }
+        }
+        "to_dot" => {
+            let path = eval_string(expr.args.first()?, lapis)?;
+            let k = nth_path_ident(&expr.receiver, 0)?;
+            let net = lapis.gmap.get(&k)?;
+            std::fs::write(path, to_dot(net, &lapis.node_names)).ok()?;
+        }
+        "to_text" => {
+            let path = eval_string(expr.args.first()?, lapis)?;
+            let k = nth_path_ident(&expr.receiver, 0)?;
+            let net = lapis.gmap.get(&k)?;
+            match to_text(net, lapis) {
+                Ok(text) => std::fs::write(path, text).ok()?,
+                Err(errors) => {
+                    for error in errors {
+                        lapis.buffer.push_str(&format!("\n// error: {}", error));
+                    }
+                }
             }
         }
         "crossfade" => {
@@ -203,7 +393,10 @@ pub fn net_methods(expr: &ExprMethodCall, lapis: &mut Lapis) -> Option<()> {
                 && unit.inputs() == net.inputs_in(id)
                 && unit.outputs() == net.outputs_in(id)
             {
+                let name = wrapped_name(&unit, lapis);
+                let net = lapis.gmap.get_mut(&k)?;
                 net.crossfade(id, fade, time, Box::new(unit));
+                lapis.node_names.insert(id, name);
             }
         }
         "connect" => {
@@ -391,23 +584,37 @@ fn method_nodeid(expr: &ExprMethodCall, lapis: &mut Lapis) -> Option<NodeId> {
             let arg = expr.args.first()?;
             let node = eval_net(arg, lapis)?;
             let k = nth_path_ident(&expr.receiver, 0)?;
+            let name = wrapped_name(&node, lapis);
             let g = lapis.gmap.get_mut(&k)?;
-            Some(g.push(Box::new(node)))
+            let id = g.push(Box::new(node));
+            lapis.net_exprs.remove(&k);
+            lapis.node_names.insert(id, name);
+            node_call(id, arg, lapis);
+            Some(id)
         }
         "chain" => {
             let arg = expr.args.first()?;
             let node = eval_net(arg, lapis)?;
             let k = nth_path_ident(&expr.receiver, 0)?;
+            let name = wrapped_name(&node, lapis);
             let g = lapis.gmap.get_mut(&k)?;
-            Some(g.chain(Box::new(node)))
+            let id = g.chain(Box::new(node));
+            lapis.net_exprs.remove(&k);
+            lapis.node_names.insert(id, name);
+            node_call(id, arg, lapis);
+            Some(id)
         }
         "fade_in" => {
             let fade = path_fade(expr.args.first()?)?;
             let fade_time = eval_float_f32(expr.args.get(1)?, lapis)?;
-            let unit = Box::new(eval_net(expr.args.get(2)?, lapis)?);
+            let unit = eval_net(expr.args.get(2)?, lapis)?;
+            let name = wrapped_name(&unit, lapis);
             let k = nth_path_ident(&expr.receiver, 0)?;
             let g = lapis.gmap.get_mut(&k)?;
-            Some(g.fade_in(fade, fade_time, unit))
+            let id = g.fade_in(fade, fade_time, Box::new(unit));
+            lapis.net_exprs.remove(&k);
+            lapis.node_names.insert(id, name);
+            Some(id)
         }
         "nth" => {
             let index = eval_usize(expr.args.first()?, lapis)?;
@@ -920,6 +1127,17 @@ fn call_net(expr: &ExprCall, lapis: &mut Lapis) -> Option<Net> {
             let release = args.get(1)?;
             Some(Net::wrap(Box::new(limiter_stereo(*attack, *release))))
         }
+        "load_net" => {
+            let path = eval_string(expr.args.first()?, lapis)?;
+            let text = std::fs::read_to_string(path).ok()?;
+            match load_net(&text, lapis) {
+                Ok(net) => Some(net),
+                Err(error) => {
+                    lapis.buffer.push_str(&format!("\n// error: load_net: {}", error));
+                    None
+                }
+            }
+        }
         "lorenz" => Some(Net::wrap(Box::new(lorenz()))),
         "lowpass" => Some(Net::wrap(Box::new(lowpass()))),
         "lowpass_hz" => {
@@ -1164,6 +1382,23 @@ fn call_net(expr: &ExprCall, lapis: &mut Lapis) -> Option<Net> {
             let f = args.first()?;
             Some(Net::wrap(Box::new(organ_hz(*f))))
         }
+        "output_loopback" => {
+            // a new stream means a new tap, so this won't follow it
+            let reader = lapis.out_tap.reader();
+            if args.len() == 1 {
+                let i = args[0] as usize;
+                let node = map(move |_: &Frame<f32, U0>| reader.at(reader.advance(), i));
+                Some(Net::wrap(Box::new(node)))
+            } else {
+                let i1 = args.first().map(|x| *x as usize).unwrap_or(0);
+                let i2 = args.get(1).map(|x| *x as usize).unwrap_or(1);
+                let node = map(move |_: &Frame<f32, U0>| {
+                    let frame = reader.advance();
+                    (reader.at(frame, i1), reader.at(frame, i2))
+                });
+                Some(Net::wrap(Box::new(node)))
+            }
+        }
         "oversample" => None, //TODO
         "pan" => {
             let p = args.first()?;
@@ -1328,7 +1563,31 @@ fn call_net(expr: &ExprCall, lapis: &mut Lapis) -> Option<Net> {
             let sharpness = eval_float_f32(expr.args.get(1)?, lapis)?;
             Some(Net::wrap(Box::new(snaredrum(seed, sharpness))))
         }
-        "snoop" => None, // TODO you shouldn't be here..
+        "snoop" => {
+            // passes its input through, and shows it in the scope under this name
+            let name = eval_string(expr.args.first()?, lapis)?;
+            let channels = if let Some(n) = expr.args.get(1) { eval_usize(n, lapis)? } else { 1 };
+            let tap = Arc::new(Tap::new(channels, TAP_FRAMES));
+            let node = match channels {
+                1 => {
+                    let tap = tap.clone();
+                    Net::wrap(Box::new(map(move |i: &Frame<f32, U1>| {
+                        tap.push(i);
+                        i[0]
+                    })))
+                }
+                2 => {
+                    let tap = tap.clone();
+                    Net::wrap(Box::new(map(move |i: &Frame<f32, U2>| {
+                        tap.push(i);
+                        (i[0], i[1])
+                    })))
+                }
+                _ => return None,
+            };
+            lapis.taps.insert(name, tap);
+            Some(node)
+        }
         "soft_saw" => Some(Net::wrap(Box::new(soft_saw()))),
         "soft_saw_hz" => {
             let f = args.first()?;
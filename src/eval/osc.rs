@@ -0,0 +1,145 @@
+use crate::eval::*;
+use rosc::{OscMessage, OscPacket, OscType, decoder, encoder};
+use std::net::UdpSocket;
+
+pub enum OscEvent {
+    // `/shared/<name> value`
+    Shared(String, f32),
+    // `/eval "code"`
+    Eval(String),
+}
+
+pub struct Osc {
+    // the address and port, and the listening thread
+    listener: Option<((String, u16), Stopper)>,
+    // whether `/eval` messages are accepted (off unless asked for)
+    allow_eval: Arc<AtomicBool>,
+    events: Receiver<OscEvent>,
+    // connected to the address messages are sent to
+    target: Option<UdpSocket>,
+    // shared variables that are sent with every update
+    pub meters: HashSet<String>,
+    // to wake up the ui when a message arrives
+    ctx: Arc<OnceLock<Context>>,
+}
+
+impl Osc {
+    pub fn new(ctx: Arc<OnceLock<Context>>) -> Self {
+        // dummy things
+        let (_, events) = bounded(1);
+        Osc {
+            listener: None,
+            allow_eval: Arc::new(AtomicBool::new(false)),
+            events,
+            target: None,
+            meters: HashSet::new(),
+            ctx,
+        }
+    }
+    // `host` is 127.0.0.1 unless messages should come from other machines
+    pub fn listen(&mut self, host: &str, port: u16, allow_eval: bool) -> Option<()> {
+        self.allow_eval.store(allow_eval, Ordering::Relaxed);
+        // the old thread holds on to its socket for a bit after it's stopped
+        if let Some(((h, p), _)) = &self.listener
+            && h == host
+            && *p == port
+        {
+            return Some(());
+        }
+        self.listener = None;
+        let socket = UdpSocket::bind((host, port)).ok()?;
+        // so the thread can notice it's stopped
+        socket.set_read_timeout(Some(std::time::Duration::from_millis(100))).ok()?;
+        let (s, r) = bounded(1024);
+        let (stopper, stopped) = Stopper::new();
+        let ctx = self.ctx.clone();
+        let allow_eval = self.allow_eval.clone();
+        std::thread::spawn(move || {
+            let mut buf = [0; decoder::MTU];
+            while !stopped.load(Ordering::Relaxed) {
+                if let Ok(n) = socket.recv(&mut buf)
+                    && let Ok((_, packet)) = decoder::decode_udp(&buf[..n])
+                {
+                    read_packet(packet, &s, allow_eval.load(Ordering::Relaxed));
+                    if let Some(ctx) = ctx.get() {
+                        ctx.request_repaint();
+                    }
+                }
+            }
+        });
+        self.events = r;
+        self.listener = Some(((host.into(), port), stopper));
+        Some(())
+    }
+    pub fn close(&mut self) {
+        self.listener = None;
+    }
+    // messages that arrived since the last call
+    pub fn events(&self) -> Vec<OscEvent> {
+        self.events.try_iter().collect()
+    }
+    // an empty address stops sending
+    pub fn set_target(&mut self, address: &str) -> Option<()> {
+        self.target = None;
+        if address.is_empty() {
+            return Some(());
+        }
+        let socket = UdpSocket::bind(("0.0.0.0", 0)).ok()?;
+        socket.connect(address).ok()?;
+        self.target = Some(socket);
+        Some(())
+    }
+    pub fn send(&self, address: &str, value: f32) -> Option<()> {
+        let socket = self.target.as_ref()?;
+        let message = OscMessage { addr: address.into(), args: vec![OscType::Float(value)] };
+        let bytes = encoder::encode(&OscPacket::Message(message)).ok()?;
+        socket.send(&bytes).ok()?;
+        Some(())
+    }
+    // send the metered variables, and the load of the output
+    pub fn send_meters(&self, smap: &HashMap<String, Shared>, stats: &StreamStats) {
+        if self.target.is_none() {
+            return;
+        }
+        for name in &self.meters {
+            if let Some(s) = smap.get(name) {
+                self.send(&format!("/shared/{}", name), s.value());
+            }
+        }
+        self.send("/lapis/load", stats.load.value());
+        self.send("/lapis/peak", stats.peak.value());
+    }
+}
+
+fn read_packet(packet: OscPacket, s: &Sender<OscEvent>, allow_eval: bool) {
+    match packet {
+        OscPacket::Message(msg) => {
+            if let Some(name) = msg.addr.strip_prefix("/shared/")
+                && let Some(value) = msg.args.first().and_then(osc_float)
+            {
+                let _ = s.try_send(OscEvent::Shared(name.into(), value));
+            } else if allow_eval
+                && msg.addr == "/eval"
+                && let Some(OscType::String(code)) = msg.args.first()
+            {
+                let _ = s.try_send(OscEvent::Eval(code.clone()));
+            }
+        }
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                read_packet(packet, s, allow_eval);
+            }
+        }
+    }
+}
+
+fn osc_float(arg: &OscType) -> Option<f32> {
+    match arg {
+        OscType::Float(x) => Some(*x),
+        OscType::Double(x) => Some(*x as f32),
+        OscType::Int(x) => Some(*x as f32),
+        OscType::Long(x) => Some(*x as f32),
+        OscType::Bool(x) => Some(*x as u8 as f32),
+        _ => None,
+    }
+}
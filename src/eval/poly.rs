@@ -0,0 +1,148 @@
+use crate::eval::*;
+
+// how long the gate of a held voice drops to 0 when it's retriggered (seconds).
+// longer than a block, so the envelope sees the drop
+const RETRIGGER: f32 = 0.003;
+
+struct Voice {
+    // the note it's holding (none if released)
+    note: Option<u8>,
+    pitch: Shared,
+    gate: Shared,
+    velocity: Shared,
+    // the gate is 0 until a bit after this time
+    retrigger: Shared,
+    // when it was last pressed or released
+    age: u64,
+}
+
+// copies of a voice net (3 inputs: pitch in hz, gate, velocity) mixed together.
+// each voice's inputs come from its own shared variables
+pub struct Poly {
+    voices: Vec<Voice>,
+    net: Net,
+    // the time of the net, set by a timer node in it
+    time: Shared,
+    clock: u64,
+}
+
+impl Poly {
+    pub fn new(voice: Net, n: usize) -> Option<Self> {
+        if voice.inputs() != 3 || voice.outputs() == 0 || n == 0 {
+            return None;
+        }
+        let mut voices = Vec::new();
+        let mut net: Option<Net> = None;
+        let time = shared(0.);
+        for _ in 0..n {
+            let (pitch, gate, velocity) = (shared(0.), shared(0.), shared(0.));
+            let retrigger = shared(-1.);
+            let gated = (var(&gate) | var(&retrigger) | var(&time))
+                >> map(|f: &Frame<f32, U3>| if f[2] < f[1] + RETRIGGER { 0. } else { f[0] });
+            let inputs = Net::wrap(Box::new(var(&pitch) | gated | var(&velocity)));
+            let v = inputs >> voice.clone();
+            net = Some(match net {
+                Some(net) => net + v,
+                None => v,
+            });
+            voices.push(Voice { note: None, pitch, gate, velocity, retrigger, age: 0 });
+        }
+        let net = net? | Net::wrap(Box::new(timer(&time)));
+        Some(Poly { voices, net, time, clock: 0 })
+    }
+    pub fn voices(&self) -> usize {
+        self.voices.len()
+    }
+    pub fn sounding(&self) -> usize {
+        self.voices.iter().filter(|v| v.note.is_some()).count()
+    }
+    pub fn has_backend(&self) -> bool {
+        self.net.has_backend()
+    }
+    pub fn backend(&mut self, sr: f64) -> NetBackend {
+        self.net.set_sample_rate(sr);
+        self.net.allocate();
+        self.net.backend()
+    }
+    // velocity is 0..127
+    pub fn note_on(&mut self, note: u8, velocity: f32) {
+        self.clock += 1;
+        // the same note again retriggers its voice. otherwise the free voice that was
+        // released the longest ago, and if none is free, steal the oldest one
+        let i = if let Some(i) = self.voices.iter().position(|v| v.note == Some(note)) {
+            i
+        } else if let Some((i, _)) = self
+            .voices
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.note.is_none())
+            .min_by_key(|(_, v)| v.age)
+        {
+            i
+        } else {
+            self.voices.iter().enumerate().min_by_key(|(_, v)| v.age).map(|(i, _)| i).unwrap_or(0)
+        };
+        let voice = &mut self.voices[i];
+        // a held voice (retriggered or stolen) needs its gate to drop for the envelope to restart
+        if voice.gate.value() > 0. {
+            voice.retrigger.set(self.time.value());
+        }
+        voice.note = Some(note);
+        voice.age = self.clock;
+        voice.pitch.set(midi_hz(note as f32));
+        voice.velocity.set(velocity / 127.);
+        voice.gate.set(1.);
+    }
+    pub fn note_off(&mut self, note: u8) {
+        self.clock += 1;
+        for voice in &mut self.voices {
+            if voice.note == Some(note) {
+                voice.note = None;
+                voice.age = self.clock;
+                voice.gate.set(0.);
+            }
+        }
+    }
+    pub fn all_off(&mut self) {
+        for voice in &mut self.voices {
+            voice.note = None;
+            voice.gate.set(0.);
+        }
+    }
+}
+
+pub fn call_poly(expr: &Expr, lapis: &mut Lapis) -> Option<Poly> {
+    match expr {
+        Expr::Call(expr) => {
+            if nth_path_ident(&expr.func, 0)? == "poly" {
+                let voice = eval_net_cloned(expr.args.first()?, lapis)?;
+                let n = eval_usize(expr.args.get(1)?, lapis)?;
+                return Poly::new(voice, n.min(128));
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+pub fn poly_methods(expr: &ExprMethodCall, lapis: &mut Lapis) -> Option<()> {
+    match expr.method.to_string().as_str() {
+        "note_on" => {
+            let note = eval_usize(expr.args.first()?, lapis)?.min(127) as u8;
+            let velocity = expr.args.get(1).and_then(|x| eval_float_f32(x, lapis)).unwrap_or(127.);
+            let k = nth_path_ident(&expr.receiver, 0)?;
+            lapis.polymap.get_mut(&k)?.note_on(note, velocity);
+        }
+        "note_off" => {
+            let note = eval_usize(expr.args.first()?, lapis)?.min(127) as u8;
+            let k = nth_path_ident(&expr.receiver, 0)?;
+            lapis.polymap.get_mut(&k)?.note_off(note);
+        }
+        "all_off" => {
+            let k = nth_path_ident(&expr.receiver, 0)?;
+            lapis.polymap.get_mut(&k)?.all_off();
+        }
+        _ => {}
+    }
+    None
+}
@@ -0,0 +1,82 @@
+use crate::eval::*;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+// (code, where to send what it printed)
+pub type EvalRequest = (String, Sender<String>);
+
+// accepts code over tcp (localhost only). a connection sends a block of code then
+// shuts down its writing side, and gets back the output of evaluating it
+pub struct EvalServer {
+    // the port, and the accepting thread
+    listener: Option<(u16, Stopper)>,
+    requests: Receiver<EvalRequest>,
+    // to wake up the ui when code arrives
+    ctx: Arc<OnceLock<Context>>,
+}
+
+impl EvalServer {
+    pub fn new(ctx: Arc<OnceLock<Context>>) -> Self {
+        // dummy things
+        let (_, requests) = bounded(1);
+        EvalServer { listener: None, requests, ctx }
+    }
+    pub fn start(&mut self, port: u16) -> Option<()> {
+        if let Some((p, _)) = &self.listener
+            && *p == port
+        {
+            return Some(());
+        }
+        self.listener = None;
+        let listener = TcpListener::bind(("127.0.0.1", port)).ok()?;
+        // so the thread can notice it's stopped
+        listener.set_nonblocking(true).ok()?;
+        let (s, r) = bounded(16);
+        let (stopper, stopped) = Stopper::new();
+        let ctx = self.ctx.clone();
+        std::thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let _ = handle(stream, &s, &ctx);
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        std::thread::sleep(Duration::from_millis(50));
+                    }
+                    Err(_) => {}
+                }
+            }
+        });
+        self.requests = r;
+        self.listener = Some((port, stopper));
+        Some(())
+    }
+    pub fn stop(&mut self) {
+        self.listener = None;
+    }
+    pub fn requests(&self) -> Vec<EvalRequest> {
+        self.requests.try_iter().collect()
+    }
+}
+
+fn handle(
+    mut stream: TcpStream,
+    s: &Sender<EvalRequest>,
+    ctx: &OnceLock<Context>,
+) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut code = String::new();
+    stream.read_to_string(&mut code)?;
+    let (reply, replied) = bounded(1);
+    if s.try_send((code, reply)).is_err() {
+        return Ok(());
+    }
+    if let Some(ctx) = ctx.get() {
+        ctx.request_repaint();
+    }
+    // the ui might be busy (or sleeping) but shouldn't keep the editor waiting forever
+    let out = replied.recv_timeout(Duration::from_secs(5)).unwrap_or_default();
+    stream.write_all(out.as_bytes())
+}
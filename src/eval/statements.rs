@@ -3,8 +3,11 @@ use cpal::traits::{DeviceTrait, HostTrait};
 use crossbeam_channel::bounded;
 use std::{thread, time::Duration};
 
-pub fn eval_stmt(s: Stmt, lapis: &mut Lapis) -> String {
+pub fn eval_stmt(mut s: Stmt, lapis: &mut Lapis) -> String {
     let mut buffer = String::new();
+    if !lapis.namespaces.is_empty() {
+        qualify_paths(&mut s, &lapis.namespaces);
+    }
     match s {
         Stmt::Local(expr) => {
             if eval_local(&expr, lapis).is_none() {
@@ -58,6 +61,16 @@ fn eval_expr(expr: Expr, lapis: &mut Lapis, buffer: &mut String) {
             seq.loop_end(),
         );
         buffer.push_str(&info);
+    } else if let Some(k) = nth_path_ident(&expr, 0)
+        && let Some(poly) = lapis.polymap.get(&k)
+    {
+        let info = format!(
+            "\n// Poly(voices: {}, sounding: {}, has_backend: {})",
+            poly.voices(),
+            poly.sounding(),
+            poly.has_backend(),
+        );
+        buffer.push_str(&info);
     } else if let Some(source) = eval_source(&expr, lapis) {
         buffer.push_str(&format!("\n// {:?}", source));
     } else if let Some(event) = eval_eventid(&expr, lapis) {
@@ -79,16 +92,57 @@ fn eval_expr(expr: Expr, lapis: &mut Lapis, buffer: &mut String) {
     } else if let Expr::MethodCall(expr) = expr {
         match expr.method.to_string().as_str() {
             "play" => {
-                if let Some(mut g) = eval_net(&expr.receiver, lapis) {
-                    let slot_outputs = lapis.slot.outputs();
-                    if g.inputs() == 0
-                        && g.outputs() == slot_outputs
-                        && let Some((config, _)) = &lapis.out_stream
-                    {
-                        g.allocate();
-                        g.set_sample_rate(config.sample_rate.0 as f64);
-                        lapis.slot.set(Fade::Smooth, 0.01, Box::new(g));
-                    }
+                if let Some(g) = eval_net(&expr.receiver, lapis) {
+                    lapis.play("main", Fade::Smooth, 0.01, g);
+                }
+            }
+            "play_fade" => {
+                if let Some(arg0) = expr.args.first()
+                    && let Some(fade) = path_fade(arg0)
+                    && let Some(arg1) = expr.args.get(1)
+                    && let Some(fade_time) = eval_float_f32(arg1, lapis)
+                    && let Some(g) = eval_net(&expr.receiver, lapis)
+                {
+                    lapis.play("main", fade, fade_time, g);
+                }
+            }
+            "play_on" => {
+                let mut fade = Fade::Smooth;
+                let mut fade_time = 0.01;
+                if let Some(arg) = expr.args.get(1)
+                    && let Some(f) = path_fade(arg)
+                {
+                    fade = f;
+                }
+                if let Some(arg) = expr.args.get(2)
+                    && let Some(t) = eval_float_f32(arg, lapis)
+                {
+                    fade_time = t;
+                }
+                if let Some(arg) = expr.args.first()
+                    && let Some(name) = eval_string(arg, lapis)
+                    && let Some(g) = eval_net(&expr.receiver, lapis)
+                {
+                    lapis.play(&name, fade, fade_time, g);
+                }
+            }
+            "live" | "live_on" => {
+                let Some(k) = nth_path_ident(&expr.receiver, 0) else { return };
+                let mut layer = String::from("main");
+                if expr.method == "live_on" {
+                    let Some(arg) = expr.args.first() else { return };
+                    let Some(name) = eval_string(arg, lapis) else { return };
+                    layer = name;
+                }
+                if let Some((config, _)) = &lapis.out_stream
+                    && let Some(g) = lapis.gmap.get_mut(&k)
+                    && !g.has_backend()
+                {
+                    g.set_sample_rate(config.sample_rate.0 as f64);
+                    g.allocate();
+                    let backend = Net::wrap(Box::new(g.backend()));
+                    lapis.live.insert(k);
+                    lapis.play(&layer, Fade::Smooth, 0.01, backend);
                 }
             }
             "drop" => {
@@ -109,6 +163,7 @@ fn eval_expr(expr: Expr, lapis: &mut Lapis, buffer: &mut String) {
                 vec_methods(&expr, lapis);
                 shared_methods(&expr, lapis);
                 seq_methods(&expr, lapis);
+                poly_methods(&expr, lapis);
             }
         }
     }
@@ -141,8 +196,14 @@ fn eval_local(expr: &Local, lapis: &mut Lapis) -> Option<()> {
             lapis.drop(&k);
             lapis.fmap.insert(k, v);
         } else if let Some(v) = eval_net(&init.expr, lapis) {
-            lapis.drop(&k);
-            lapis.gmap.insert(k, v);
+            let recorded = resolve_net_expr(&init.expr, lapis);
+            if lapis.live.contains(&k) {
+                lapis.merge_live(&k, v);
+            } else {
+                lapis.drop(&k);
+                lapis.gmap.insert(k.clone(), v);
+            }
+            lapis.record_net(k, recorded);
         } else if let Some(arr) = eval_vec(&init.expr, lapis) {
             lapis.drop(&k);
             lapis.vmap.insert(k, arr);
@@ -164,6 +225,9 @@ fn eval_local(expr: &Local, lapis: &mut Lapis) -> Option<()> {
         } else if let Some(seq) = call_seq(&init.expr, lapis) {
             lapis.drop(&k);
             lapis.seqmap.insert(k, seq);
+        } else if let Some(poly) = call_poly(&init.expr, lapis) {
+            lapis.drop(&k);
+            lapis.polymap.insert(k, poly);
         } else if let Some(source) = eval_source(&init.expr, lapis) {
             lapis.drop(&k);
             lapis.srcmap.insert(k, source);
@@ -228,7 +292,13 @@ fn eval_assign(expr: &ExprAssign, lapis: &mut Lapis) {
                 }
             } else if lapis.gmap.contains_key(&ident) {
                 if let Some(g) = eval_net(&expr.right, lapis) {
-                    lapis.gmap.insert(ident, g);
+                    let recorded = resolve_net_expr(&expr.right, lapis);
+                    if lapis.live.contains(&ident) {
+                        lapis.merge_live(&ident, g);
+                    } else {
+                        lapis.gmap.insert(ident.clone(), g);
+                    }
+                    lapis.record_net(ident, recorded);
                 }
             } else if lapis.vmap.contains_key(&ident) {
                 if let Some(a) = eval_vec(&expr.right, lapis) {
@@ -277,6 +347,12 @@ fn eval_assign(expr: &ExprAssign, lapis: &mut Lapis) {
                         "keys" => lapis.keys_active = b,
                         "quiet" => lapis.quiet = b,
                         "keys_repeat" => lapis.keys_repeat = b,
+                        "scope" => lapis.scope_window = b,
+                        "spectrum" => lapis.spectrum_window = b,
+                        "inspector" => lapis.inspector = b,
+                        "graph" => lapis.graph_window = b,
+                        "limiter" => lapis.protection.limiter.store(b, Ordering::Relaxed),
+                        "dc_block" => lapis.protection.dc_block.store(b, Ordering::Relaxed),
                         _ => {}
                     }
                 } else if let Some(right) = eval_string(&expr.right, lapis)
@@ -386,13 +462,286 @@ fn function_calls(expr: ExprCall, lapis: &mut Lapis, buffer: &mut String) -> Opt
             let buffer = eval_usize(expr.args.get(4)?, lapis).map(|x| x as u32);
             lapis.set_out_device(h, d, channels, sr, buffer);
         }
+        #[cfg(not(target_arch = "wasm32"))]
+        "set_null_out" => {
+            let channels = eval_usize(expr.args.first()?, lapis).map(|x| x as u16);
+            let sr = eval_usize(expr.args.get(1)?, lapis).map(|x| x as u32);
+            let buffer = eval_usize(expr.args.get(2)?, lapis).map(|x| x as u32);
+            lapis.set_null_out(channels, sr, buffer);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        "set_in_file" => {
+            let path = eval_string(expr.args.first()?, lapis)?;
+            lapis.set_in_file(&path);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        "midi_list" => buffer.push_str(&Midi::list_ports()),
+        #[cfg(not(target_arch = "wasm32"))]
+        "midi_in" => {
+            let index = eval_usize(expr.args.first()?, lapis)?;
+            if lapis.midi.connect(index).is_none() {
+                buffer.push_str(&format!("\n// error: couldn't connect to midi input {}", index));
+            }
+        }
+        #[cfg(unix)]
+        "midi_in_virtual" => {
+            let name = eval_string(expr.args.first()?, lapis)?;
+            if lapis.midi.connect_virtual(&name).is_none() {
+                buffer.push_str(&format!("\n// error: couldn't make the midi port {}", name));
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        "midi_close" => lapis.midi.disconnect(),
+        #[cfg(not(target_arch = "wasm32"))]
+        "midi_clear" => lapis.midi.clear(),
+        #[cfg(not(target_arch = "wasm32"))]
+        "midi_cc" => {
+            let channel = eval_usize(expr.args.first()?, lapis)? as u8;
+            let cc = eval_usize(expr.args.get(1)?, lapis)? as u8;
+            let name = eval_string(expr.args.get(2)?, lapis)?;
+            if name.is_empty() {
+                lapis.midi.bind_cc(channel, cc, None);
+            } else {
+                let var = lapis.smap.get(&name)?.clone();
+                let min = expr.args.get(3).and_then(|x| eval_float_f32(x, lapis)).unwrap_or(0.);
+                let max = expr.args.get(4).and_then(|x| eval_float_f32(x, lapis)).unwrap_or(1.);
+                lapis.midi.bind_cc(channel, cc, Some((var, min, max)));
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        "midi_on" | "midi_off" => {
+            let channel = eval_usize(expr.args.first()?, lapis)? as u8;
+            let code = eval_string(expr.args.get(1)?, lapis)?;
+            let map =
+                if func == "midi_on" { &mut lapis.midi.note_on } else { &mut lapis.midi.note_off };
+            if code.is_empty() {
+                map.remove(&channel);
+            } else {
+                map.insert(channel, code);
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        "osc_listen" => {
+            let port = eval_usize(expr.args.first()?, lapis)?;
+            let allow_eval = expr.args.get(1).and_then(|x| eval_bool(x, lapis)).unwrap_or(false);
+            let host = match expr.args.get(2) {
+                Some(x) => eval_string(x, lapis)?,
+                None => String::from("127.0.0.1"),
+            };
+            lapis.osc.listen(&host, port as u16, allow_eval);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        "osc_close" => lapis.osc.close(),
+        #[cfg(not(target_arch = "wasm32"))]
+        "osc_target" => {
+            let address = eval_string(expr.args.first()?, lapis)?;
+            lapis.osc.set_target(&address);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        "osc_send" => {
+            let address = eval_string(expr.args.first()?, lapis)?;
+            let value = eval_float_f32(expr.args.get(1)?, lapis)?;
+            lapis.osc.send(&address, value);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        "osc_meter" => {
+            let name = eval_string(expr.args.first()?, lapis)?;
+            if expr.args.get(1).and_then(|x| eval_bool(x, lapis)).unwrap_or(true) {
+                lapis.osc.meters.insert(name);
+            } else {
+                lapis.osc.meters.remove(&name);
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        "eval_server" => {
+            let port = eval_usize(expr.args.first()?, lapis)?;
+            lapis.server.start(port as u16);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        "eval_server_stop" => lapis.server.stop(),
+        "include" => {
+            let path = eval_string(expr.args.first()?, lapis)?;
+            let namespace = expr.args.get(1).and_then(|x| eval_string(x, lapis));
+            let out = lapis.run_file(&path, namespace.as_deref());
+            buffer.push_str(&out);
+        }
+        "watch" => {
+            let path = eval_string(expr.args.first()?, lapis)?;
+            // relative to the file that's being evaluated, like `include`
+            let path = lapis.relative_path(&path);
+            let Ok(path) = path.canonicalize() else {
+                buffer.push_str(&format!("\n// can't find {}", path.display()));
+                return None;
+            };
+            let path = path.to_string_lossy().into_owned();
+            if expr.args.get(1).and_then(|x| eval_bool(x, lapis)).unwrap_or(true) {
+                // evaluated on the next update
+                lapis.watched.entry(path).or_insert(None);
+            } else {
+                lapis.watched.remove(&path);
+            }
+        }
         "add_slider" => {
             let var = eval_string(expr.args.first()?, lapis)?;
             let min = eval_float_f32(expr.args.get(1)?, lapis)?;
             let max = eval_float_f32(expr.args.get(2)?, lapis)?;
             let speed = eval_float(expr.args.get(3)?, lapis)?;
             let step_by = eval_float(expr.args.get(4)?, lapis)?;
-            lapis.sliders.push(SliderSettings { min, max, speed, step_by, var });
+            // a slider for the same variable is updated (keeping its curve, midi and recording)
+            if let Some(s) = lapis.sliders.iter_mut().find(|s| s.var == var) {
+                (s.min, s.max, s.speed, s.step_by) = (min, max, speed, step_by);
+            } else {
+                lapis.sliders.push(SliderSettings {
+                    min,
+                    max,
+                    speed,
+                    step_by,
+                    var,
+                    ..Default::default()
+                });
+            }
+        }
+        "slider_curve" => {
+            let var = eval_string(expr.args.first()?, lapis)?;
+            let arg = expr.args.get(1)?;
+            // "linear", "log", or a power
+            let curve = if let Some(name) = eval_string(arg, lapis) {
+                match name.as_str() {
+                    "linear" => Curve::Linear,
+                    "log" => Curve::Log,
+                    _ => return None,
+                }
+            } else {
+                let p = eval_float_f32(arg, lapis)?;
+                if p <= 0. {
+                    return None;
+                }
+                Curve::Power(p)
+            };
+            for s in lapis.sliders.iter_mut().filter(|s| s.var == var) {
+                s.curve = curve;
+            }
+        }
+        "slider_unit" => {
+            let var = eval_string(expr.args.first()?, lapis)?;
+            let unit = eval_string(expr.args.get(1)?, lapis)?;
+            for s in lapis.sliders.iter_mut().filter(|s| s.var == var) {
+                s.unit = unit.clone();
+            }
+        }
+        "slider_cc" => {
+            let var = eval_string(expr.args.first()?, lapis)?;
+            // no channel and cc removes the binding
+            let cc = match (expr.args.get(1), expr.args.get(2)) {
+                (Some(channel), Some(cc)) => {
+                    Some((eval_usize(channel, lapis)? as u8, eval_usize(cc, lapis)? as u8))
+                }
+                _ => None,
+            };
+            for s in lapis.sliders.iter_mut().filter(|s| s.var == var) {
+                s.cc = cc;
+            }
+        }
+        "slider_loop" => {
+            let var = eval_string(expr.args.first()?, lapis)?;
+            let on = eval_bool(expr.args.get(1)?, lapis)?;
+            for s in lapis.sliders.iter_mut().filter(|s| s.var == var) {
+                let a = &mut s.automation;
+                if a.recording.is_none() {
+                    a.playing = on && !a.points.is_empty();
+                    a.start = None;
+                }
+            }
+        }
+        "add_knob" => {
+            let var = eval_string(expr.args.first()?, lapis)?;
+            let min = eval_float_f32(expr.args.get(1)?, lapis)?;
+            let max = eval_float_f32(expr.args.get(2)?, lapis)?;
+            upsert_widget(&mut lapis.widgets, Widget::Knob { var, min, max });
+        }
+        "add_toggle" => {
+            let var = eval_string(expr.args.first()?, lapis)?;
+            upsert_widget(&mut lapis.widgets, Widget::Toggle { var });
+        }
+        "add_button" => {
+            let label = eval_string(expr.args.first()?, lapis)?;
+            let code = eval_string(expr.args.get(1)?, lapis)?;
+            upsert_widget(&mut lapis.widgets, Widget::Button { label, code });
+        }
+        "add_xy" => {
+            let x = eval_string(expr.args.first()?, lapis)?;
+            let y = eval_string(expr.args.get(1)?, lapis)?;
+            let min = eval_float_f32(expr.args.get(2)?, lapis)?;
+            let max = eval_float_f32(expr.args.get(3)?, lapis)?;
+            upsert_widget(&mut lapis.widgets, Widget::Xy { x, y, min, max });
+        }
+        "add_multi" => {
+            let var = eval_string(expr.args.first()?, lapis)?;
+            let min = eval_float_f32(expr.args.get(1)?, lapis)?;
+            let max = eval_float_f32(expr.args.get(2)?, lapis)?;
+            upsert_widget(&mut lapis.widgets, Widget::Multi { var, min, max });
+        }
+        "panel" => {
+            let name = eval_string(expr.args.first()?, lapis)?;
+            lapis.panel(name).open = true;
+        }
+        "panel_slider" => {
+            let name = eval_string(expr.args.first()?, lapis)?;
+            let var = eval_string(expr.args.get(1)?, lapis)?;
+            let min = eval_float_f32(expr.args.get(2)?, lapis)?;
+            let max = eval_float_f32(expr.args.get(3)?, lapis)?;
+            upsert_widget(&mut lapis.panel(name).widgets, Widget::Slider { var, min, max });
+        }
+        "panel_knob" => {
+            let name = eval_string(expr.args.first()?, lapis)?;
+            let var = eval_string(expr.args.get(1)?, lapis)?;
+            let min = eval_float_f32(expr.args.get(2)?, lapis)?;
+            let max = eval_float_f32(expr.args.get(3)?, lapis)?;
+            upsert_widget(&mut lapis.panel(name).widgets, Widget::Knob { var, min, max });
+        }
+        "panel_toggle" => {
+            let name = eval_string(expr.args.first()?, lapis)?;
+            let var = eval_string(expr.args.get(1)?, lapis)?;
+            upsert_widget(&mut lapis.panel(name).widgets, Widget::Toggle { var });
+        }
+        "panel_button" => {
+            let name = eval_string(expr.args.first()?, lapis)?;
+            let label = eval_string(expr.args.get(1)?, lapis)?;
+            let code = eval_string(expr.args.get(2)?, lapis)?;
+            upsert_widget(&mut lapis.panel(name).widgets, Widget::Button { label, code });
+        }
+        "panel_label" => {
+            let name = eval_string(expr.args.first()?, lapis)?;
+            let text = eval_string(expr.args.get(1)?, lapis)?;
+            upsert_widget(&mut lapis.panel(name).widgets, Widget::Label { text });
+        }
+        "panel_remove" => {
+            let name = eval_string(expr.args.first()?, lapis)?;
+            lapis.panels.retain(|p| p.name != name);
+        }
+        "stop" => {
+            let name = eval_string(expr.args.first()?, lapis)?;
+            lapis.remove_layer(&name);
+        }
+        "mute" => {
+            let name = eval_string(expr.args.first()?, lapis)?;
+            let mute = eval_bool(expr.args.get(1)?, lapis)?;
+            let layer = lapis.layers.get(&name)?;
+            layer.mute.set(if mute { 1. } else { 0. });
+        }
+        "set_gain" => {
+            let name = eval_string(expr.args.first()?, lapis)?;
+            let gain = eval_float_f32(expr.args.get(1)?, lapis)?;
+            let layer = lapis.layers.get(&name)?;
+            layer.gain.set(gain);
+        }
+        "tempo" => {
+            let bpm = eval_float_f32(expr.args.first()?, lapis)?;
+            lapis.tempo.set(bpm.max(0.));
+        }
+        "quantize" => {
+            let beats = eval_float(expr.args.first()?, lapis)?;
+            lapis.quantize = beats.max(0.);
         }
         "drop_in_stream" => lapis.in_stream = None,
         "drop_out_stream" => lapis.out_stream = None,
@@ -423,6 +772,24 @@ fn function_calls(expr: ExprCall, lapis: &mut Lapis, buffer: &mut String) -> Opt
             lapis.clear_sliders();
             lapis.clear_keys();
             lapis.clear_maps();
+            #[cfg(not(target_arch = "wasm32"))]
+            lapis.midi.clear();
+        }
+        "export_rust" => {
+            let path = eval_string(expr.args.first()?, lapis)?;
+            let k = nth_path_ident(expr.args.get(1)?, 0)?;
+            match export_rust(&k, lapis) {
+                Ok(code) => {
+                    if let Err(e) = std::fs::write(&path, code) {
+                        buffer.push_str(&format!("\n// error: couldn't write {}: {}", path, e));
+                    }
+                }
+                Err(errors) => {
+                    for error in errors {
+                        buffer.push_str(&format!("\n// error: {}", error));
+                    }
+                }
+            }
         }
         "zoom_factor" => {
             let factor = eval_float_f32(expr.args.first()?, lapis)?;
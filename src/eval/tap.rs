@@ -0,0 +1,97 @@
+use crate::eval::*;
+
+// frames kept by the output tap and snoops
+pub const TAP_FRAMES: usize = 1 << 15;
+
+// the recent frames of a signal. written on the audio thread, read anywhere
+pub struct Tap {
+    channels: usize,
+    frames: usize,
+    // frames written so far
+    written: AtomicUsize,
+    // interleaved f32 bits
+    ring: Vec<AtomicU32>,
+}
+
+impl Tap {
+    pub fn new(channels: usize, frames: usize) -> Self {
+        let ring = (0..frames * channels).map(|_| AtomicU32::new(0)).collect();
+        Tap { channels, frames, written: AtomicUsize::new(0), ring }
+    }
+    pub fn write(&self, block: &BufferVec, size: usize) {
+        let written = self.written.load(Ordering::Relaxed);
+        for i in 0..size {
+            let frame = (written + i) % self.frames * self.channels;
+            for ch in 0..self.channels {
+                self.ring[frame + ch].store(block.at_f32(ch, i).to_bits(), Ordering::Relaxed);
+            }
+        }
+        self.written.store(written + size, Ordering::Relaxed);
+    }
+    pub fn push(&self, frame: &[f32]) {
+        let written = self.written.load(Ordering::Relaxed);
+        let start = written % self.frames * self.channels;
+        for (ch, s) in frame.iter().take(self.channels).enumerate() {
+            self.ring[start + ch].store(s.to_bits(), Ordering::Relaxed);
+        }
+        self.written.store(written + 1, Ordering::Relaxed);
+    }
+    fn at(&self, frame: usize, channel: usize) -> f32 {
+        if channel < self.channels {
+            let bits = self.ring[frame % self.frames * self.channels + channel];
+            f32::from_bits(bits.load(Ordering::Relaxed))
+        } else {
+            0.
+        }
+    }
+    // the last `n` frames of each channel (oldest first). a block's worth is left out
+    // of the ring so it doesn't read what's being written
+    pub fn read(&self, n: usize) -> Vec<Vec<f32>> {
+        self.read_counted(n).1
+    }
+    // the same, with the number of frames written when it was read (where the frames end)
+    pub fn read_counted(&self, n: usize) -> (usize, Vec<Vec<f32>>) {
+        let written = self.written.load(Ordering::Relaxed);
+        let n = n.min(written).min(self.frames.saturating_sub(MAX_BUFFER_SIZE));
+        let start = written - n;
+        let data = (0..self.channels).map(|ch| (start..written).map(|i| self.at(i, ch)).collect());
+        (written, data.collect())
+    }
+    pub fn written(&self) -> usize {
+        self.written.load(Ordering::Relaxed)
+    }
+    pub fn reader(self: &Arc<Self>) -> TapReader {
+        TapReader { tap: self.clone(), pos: AtomicUsize::new(0) }
+    }
+}
+
+// follows the tap one block (MAX_BUFFER_SIZE frames) behind. in the mixer it
+// processes as many frames as get written to the output tap, so it stays in step
+pub struct TapReader {
+    tap: Arc<Tap>,
+    pos: AtomicUsize,
+}
+
+impl Clone for TapReader {
+    fn clone(&self) -> Self {
+        let pos = AtomicUsize::new(self.pos.load(Ordering::Relaxed));
+        TapReader { tap: self.tap.clone(), pos }
+    }
+}
+
+impl TapReader {
+    // move to the next frame and return its position
+    pub fn advance(&self) -> usize {
+        let written = self.tap.written.load(Ordering::Relaxed);
+        let mut pos = self.pos.load(Ordering::Relaxed);
+        // out of step (it isn't playing, or it just started)
+        if pos >= written || written - pos > MAX_BUFFER_SIZE {
+            pos = written.saturating_sub(MAX_BUFFER_SIZE);
+        }
+        self.pos.store(pos + 1, Ordering::Relaxed);
+        pos
+    }
+    pub fn at(&self, frame: usize, channel: usize) -> f32 {
+        self.tap.at(frame, channel)
+    }
+}
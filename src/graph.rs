@@ -0,0 +1,169 @@
+use crate::eval::*;
+use eframe::egui::*;
+use fundsp::hacker::{AudioUnit, Net, NodeId, Source};
+use std::collections::HashMap;
+
+const WIDTH: f32 = 130.;
+const PORT: f32 = 14.;
+const HEADER: f32 = 34.;
+const GAP: Vec2 = vec2(60., 16.);
+
+struct Node {
+    label: String,
+    ins: usize,
+    outs: usize,
+    // where each input comes from. none for the global inputs node
+    sources: Vec<Source>,
+    column: usize,
+    rect: Rect,
+}
+
+impl Node {
+    fn input(&self, i: usize) -> Pos2 {
+        pos2(self.rect.left(), self.rect.top() + HEADER + (i as f32 + 0.5) * PORT)
+    }
+    fn output(&self, i: usize) -> Pos2 {
+        pos2(self.rect.right(), self.rect.top() + HEADER + (i as f32 + 0.5) * PORT)
+    }
+}
+
+// the nodes of a net laid out in columns, the global inputs first and the global outputs last
+fn layout(net: &Net, names: &HashMap<NodeId, String>) -> Vec<Node> {
+    let ids: Vec<NodeId> = net.ids().copied().collect();
+    let mut nodes = vec![Node {
+        label: "inputs".into(),
+        ins: 0,
+        outs: net.inputs(),
+        sources: Vec::new(),
+        column: 0,
+        rect: Rect::NOTHING,
+    }];
+    for id in &ids {
+        let name = names.get(id).map_or("?", |n| n.as_str());
+        let ins = net.inputs_in(*id);
+        nodes.push(Node {
+            label: format!("{:?}\n{}", id, name),
+            ins,
+            outs: net.outputs_in(*id),
+            sources: (0..ins).map(|i| net.source(*id, i)).collect(),
+            column: 1,
+            rect: Rect::NOTHING,
+        });
+    }
+    nodes.push(Node {
+        label: "outputs".into(),
+        ins: net.outputs(),
+        outs: 0,
+        sources: (0..net.outputs()).map(|i| net.output_source(i)).collect(),
+        column: 1,
+        rect: Rect::NOTHING,
+    });
+    // each node goes one column after the furthest node it reads from
+    let index = |id: &NodeId| ids.iter().position(|i| i == id).map(|i| i + 1);
+    for _ in 0..nodes.len() {
+        let mut changed = false;
+        for n in 1..nodes.len() {
+            let column = nodes[n]
+                .sources
+                .iter()
+                .filter_map(|s| match s {
+                    Source::Local(id, _) => index(id).map(|i| nodes[i].column + 1),
+                    _ => None,
+                })
+                .fold(1, usize::max);
+            if column != nodes[n].column {
+                nodes[n].column = column;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    let last = nodes.len() - 1;
+    nodes[last].column = nodes.iter().map(|n| n.column).max().unwrap_or(0) + 1;
+    let mut heights = HashMap::new();
+    for node in &mut nodes {
+        let y: &mut f32 = heights.entry(node.column).or_default();
+        let height = HEADER + node.ins.max(node.outs) as f32 * PORT + 4.;
+        let min = pos2(node.column as f32 * (WIDTH + GAP.x), *y);
+        node.rect = Rect::from_min_size(min, vec2(WIDTH, height));
+        *y += height + GAP.y;
+    }
+    nodes
+}
+
+impl Lapis {
+    pub fn graph_ui(&mut self, ui: &mut Ui) {
+        let mut names: Vec<&String> = self.gmap.keys().collect();
+        names.sort();
+        let selected = if self.graph_net.is_empty() { "net" } else { &self.graph_net };
+        ComboBox::from_id_salt("graph net").selected_text(selected.to_string()).show_ui(ui, |ui| {
+            for name in names {
+                ui.selectable_value(&mut self.graph_net, name.clone(), name);
+            }
+        });
+        let Some(net) = self.gmap.get(&self.graph_net) else { return };
+        let nodes = layout(net, &self.node_names);
+        let ids: Vec<NodeId> = net.ids().copied().collect();
+        let size = nodes.iter().fold(Rect::NOTHING, |r, n| r.union(n.rect)).size();
+        ScrollArea::both().show(ui, |ui| {
+            let (response, painter) = ui.allocate_painter(size + vec2(8., 8.), Sense::hover());
+            let offset = response.rect.min.to_vec2() + vec2(4., 4.);
+            let visuals = ui.visuals();
+            let text = visuals.text_color();
+            let weak = visuals.weak_text_color();
+            let edge = Stroke::new(1., visuals.selection.bg_fill);
+            let font = FontId::monospace(11.);
+            for node in &nodes {
+                let rect = node.rect.translate(offset);
+                painter.rect_filled(rect, 4., visuals.faint_bg_color);
+                painter.rect_stroke(rect, 4., (1., weak), StrokeKind::Inside);
+                painter.text(
+                    rect.min + vec2(4., 2.),
+                    Align2::LEFT_TOP,
+                    &node.label,
+                    font.clone(),
+                    text,
+                );
+                let io = format!("{} -> {}", node.ins, node.outs);
+                painter.text(
+                    rect.right_top() + vec2(-4., 2.),
+                    Align2::RIGHT_TOP,
+                    io,
+                    font.clone(),
+                    weak,
+                );
+                for i in 0..node.ins {
+                    let p = node.input(i) + offset;
+                    painter.circle_filled(p, 3., weak);
+                    painter.text(p + vec2(5., 0.), Align2::LEFT_CENTER, i, font.clone(), weak);
+                }
+                for i in 0..node.outs {
+                    let p = node.output(i) + offset;
+                    painter.circle_filled(p, 3., weak);
+                    painter.text(p - vec2(5., 0.), Align2::RIGHT_CENTER, i, font.clone(), weak);
+                }
+                for (i, source) in node.sources.iter().enumerate() {
+                    let from = match source {
+                        Source::Local(id, chan) => {
+                            ids.iter().position(|i| i == id).map(|n| nodes[n + 1].output(*chan))
+                        }
+                        Source::Global(chan) => Some(nodes[0].output(*chan)),
+                        Source::Zero => None,
+                    };
+                    if let Some(from) = from {
+                        let (from, to) = (from + offset, node.input(i) + offset);
+                        let bend = vec2(((to.x - from.x) / 2.).abs().max(20.), 0.);
+                        painter.add(CubicBezierShape::from_points_stroke(
+                            [from, from + bend, to - bend, to],
+                            false,
+                            Color32::TRANSPARENT,
+                            edge,
+                        ));
+                    }
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,136 @@
+use crate::eval::*;
+use eframe::egui::*;
+use fundsp::hacker::{AudioUnit, Fade};
+
+enum Action {
+    Drop(String),
+    Play(String),
+    #[cfg(feature = "plot")]
+    Plot(String),
+}
+
+impl Lapis {
+    // (name, type, summary) of everything that's defined, sorted by name
+    fn bindings(&self) -> Vec<(String, &'static str, String)> {
+        let mut list = Vec::new();
+        for (k, v) in &self.fmap {
+            list.push((k.clone(), "float", v.to_string()));
+        }
+        for (k, v) in &self.bmap {
+            list.push((k.clone(), "bool", v.to_string()));
+        }
+        for (k, v) in &self.smap {
+            list.push((k.clone(), "shared", v.value().to_string()));
+        }
+        for (k, v) in &self.vmap {
+            list.push((k.clone(), "vec", format!("len: {}", v.len())));
+        }
+        for (k, g) in &self.gmap {
+            let live = if self.live.contains(k) { ", live" } else { "" };
+            let info =
+                format!("ins: {}, outs: {}, size: {}{}", g.inputs(), g.outputs(), g.size(), live);
+            list.push((k.clone(), "net", info));
+        }
+        for (k, w) in &self.wmap {
+            let info = format!("ch: {}, dur: {:.3}s", w.channels(), w.duration());
+            list.push((k.clone(), "wave", info));
+        }
+        for (k, seq) in &self.seqmap {
+            let info = format!("outs: {}, has_backend: {}", seq.outputs(), seq.has_backend());
+            list.push((k.clone(), "sequencer", info));
+        }
+        for (k, poly) in &self.polymap {
+            let info = format!("voices: {}, sounding: {}", poly.voices(), poly.sounding());
+            list.push((k.clone(), "poly", info));
+        }
+        for (k, v) in &self.string_map {
+            list.push((k.clone(), "string", format!("{:?}", v)));
+        }
+        for (k, t) in &self.atomic_table_map {
+            list.push((k.clone(), "table", format!("len: {}", t.len())));
+        }
+        for (k, id) in &self.idmap {
+            list.push((k.clone(), "node", format!("{:?}", id)));
+        }
+        for (k, e) in &self.eventmap {
+            list.push((k.clone(), "event", format!("{:?}", e)));
+        }
+        for (k, s) in &self.srcmap {
+            list.push((k.clone(), "source", format!("{:?}", s)));
+        }
+        list.sort();
+        list
+    }
+    pub fn inspector_ui(&mut self, ui: &mut Ui) {
+        ui.add(TextEdit::singleline(&mut self.inspector_filter).hint_text("filter"));
+        let filter = self.inspector_filter.clone();
+        let mut action = None;
+        ScrollArea::vertical().show(ui, |ui| {
+            Grid::new("inspector").striped(true).num_columns(4).show(ui, |ui| {
+                for (name, kind, summary) in self.bindings() {
+                    if !name.contains(&filter) {
+                        continue;
+                    }
+                    ui.monospace(&name);
+                    ui.label(kind);
+                    match kind {
+                        "float" => {
+                            if let Some(v) = self.fmap.get_mut(&name) {
+                                ui.add(DragValue::new(v).speed(0.01));
+                            }
+                        }
+                        "bool" => {
+                            if let Some(v) = self.bmap.get_mut(&name) {
+                                ui.checkbox(v, "");
+                            }
+                        }
+                        "shared" => {
+                            if let Some(s) = self.smap.get(&name) {
+                                let mut v = s.value();
+                                if ui.add(DragValue::new(&mut v).speed(0.01)).changed() {
+                                    s.set(v);
+                                }
+                            }
+                        }
+                        "vec" => {
+                            ui.label(&summary).on_hover_text(format!("{:?}", self.vmap[&name]));
+                        }
+                        _ => {
+                            ui.label(&summary);
+                        }
+                    }
+                    ui.horizontal(|ui| {
+                        if kind == "net" {
+                            if ui.small_button("play").clicked() {
+                                action = Some(Action::Play(name.clone()));
+                            }
+                            #[cfg(feature = "plot")]
+                            if ui.small_button("plot").on_hover_text("plot one second").clicked() {
+                                action = Some(Action::Plot(name.clone()));
+                            }
+                        }
+                        if ui.small_button("drop").clicked() {
+                            action = Some(Action::Drop(name.clone()));
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+        });
+        match action {
+            Some(Action::Drop(name)) => self.drop(&name),
+            Some(Action::Play(name)) => {
+                if let Some(g) = self.gmap.get(&name) {
+                    self.play("main", Fade::Smooth, 0.01, g.clone());
+                }
+            }
+            #[cfg(feature = "plot")]
+            Some(Action::Plot(name)) => {
+                // a clone, so the variable isn't used up
+                let sr = self.out_stream.as_ref().map_or(44100, |s| s.0.sample_rate.0);
+                self.quiet_eval(&format!("plot({name}.clone(), 1, {sr}, -1, 1, 0, \"{name}\");"));
+            }
+            None => {}
+        }
+    }
+}
@@ -2,12 +2,23 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use eframe::egui::*;
+use std::sync::atomic::Ordering;
 
+#[cfg(not(target_arch = "wasm32"))]
+mod args;
 mod eval;
+mod graph;
+mod inspector;
+mod scope;
+mod settings;
+mod sliders;
+mod spectrum;
+mod widgets;
 use eval::*;
 
 #[cfg(not(target_arch = "wasm32"))]
 fn main() -> eframe::Result {
+    let Some(args) = args::Args::parse() else { return Ok(()) };
     let options = eframe::NativeOptions {
         viewport: ViewportBuilder {
             inner_size: Some(Vec2::new(550., 445.)),
@@ -22,11 +33,20 @@ fn main() -> eframe::Result {
         centered: true,
         ..Default::default()
     };
-    let mut lapis = Lapis::new();
-    if let Ok(init) = std::fs::read_to_string("init.rs") {
-        lapis.eval(&init);
-    }
-    eframe::run_native("awawawa", options, Box::new(|_| Ok(Box::new(lapis))))
+    let mut lapis = Lapis::with_streams(args.streams());
+    eframe::run_native(
+        "awawawa",
+        options,
+        Box::new(move |cc| {
+            let _ = lapis.ctx.set(cc.egui_ctx.clone());
+            // restore the saved settings first, so the options and scripts can override them
+            if let Some(storage) = cc.storage {
+                lapis.load_settings(storage);
+            }
+            args.apply(&mut lapis);
+            Ok(Box::new(lapis))
+        }),
+    )
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -48,7 +68,17 @@ fn main() {
             .expect("the_canvas_id was not a HtmlCanvasElement");
 
         let start_result = eframe::WebRunner::new()
-            .start(canvas, web_options, Box::new(|_| Ok(Box::new(Lapis::new()))))
+            .start(
+                canvas,
+                web_options,
+                Box::new(|cc| {
+                    let mut lapis = Lapis::with_streams(StreamOptions::default());
+                    if let Some(storage) = cc.storage {
+                        lapis.load_settings(storage);
+                    }
+                    Ok(Box::new(lapis))
+                }),
+            )
             .await;
 
         // Remove the loading text and spinner:
@@ -69,6 +99,9 @@ fn main() {
 }
 
 impl eframe::App for Lapis {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.save_settings(storage);
+    }
     fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
         let center = Align2::CENTER_CENTER;
         let mut theme = egui_extras::syntax_highlighting::CodeTheme::from_memory(ctx, &ctx.style());
@@ -84,6 +117,13 @@ impl eframe::App for Lapis {
             layout_job.wrap.max_width = wrap_width;
             ui.fonts_mut(|f| f.layout_job(layout_job))
         };
+        self.check_faults();
+        #[cfg(target_arch = "wasm32")]
+        self.free_garbage();
+        if !self.watched.is_empty() {
+            self.check_watched();
+            ctx.request_repaint_after(std::time::Duration::from_millis(250));
+        }
         if self.keys_active {
             ctx.input(|i| {
                 for event in &i.events {
@@ -102,6 +142,19 @@ impl eframe::App for Lapis {
                 }
             });
         }
+        #[cfg(not(target_arch = "wasm32"))]
+        self.update_osc();
+        #[cfg(not(target_arch = "wasm32"))]
+        self.update_server();
+        #[cfg(not(target_arch = "wasm32"))]
+        for code in self.midi.note_code() {
+            if self.quiet {
+                self.quiet_eval(&code);
+            } else {
+                self.eval(&code);
+            }
+        }
+        self.update_sliders(ctx);
         TopBottomPanel::bottom("input")
             .resizable(true)
             .show_separator_line(false)
@@ -173,10 +226,36 @@ impl eframe::App for Lapis {
                 if ui.button("sliders").clicked() {
                     self.sliders_window = !self.sliders_window;
                 }
+                if ui.button("scope").clicked() {
+                    self.scope_window = !self.scope_window;
+                }
+                if ui.button("spectrum").clicked() {
+                    self.spectrum_window = !self.spectrum_window;
+                }
+                if ui.button("graph").clicked() {
+                    self.graph_window = !self.graph_window;
+                }
+                if ui.button("inspector").clicked() {
+                    self.inspector = !self.inspector;
+                }
                 if ui.button("about").clicked() {
                     self.about = !self.about;
                 }
                 ui.with_layout(Layout::right_to_left(Align::TOP), |ui| {
+                    if self.out_stream.is_some() {
+                        ctx.request_repaint_after(std::time::Duration::from_millis(200));
+                        let stats = &self.out_stats;
+                        ui.monospace(format!(
+                            "dsp {:>3.0}% peak {:>3.0}% xruns {} clips {}",
+                            stats.load.value() * 100.,
+                            stats.peak.value() * 100.,
+                            stats.xruns.load(Ordering::Relaxed),
+                            stats.clips.load(Ordering::Relaxed),
+                        ))
+                        .on_hover_text(
+                            "output callback load (average, peak), xruns, and clipped samples",
+                        );
+                    }
                     ui.toggle_value(&mut self.keys_repeat, "keys repeat?")
                         .on_hover_text("enable key repeat events");
                     ui.toggle_value(&mut self.keys_active, "keys?")
@@ -186,6 +265,11 @@ impl eframe::App for Lapis {
                 });
             });
         });
+        if self.inspector {
+            SidePanel::right("inspector").resizable(true).show(ctx, |ui| {
+                self.inspector_ui(ui);
+            });
+        }
         CentralPanel::default().show(ctx, |ui| {
             ctx.set_zoom_factor(self.zoom_factor);
             Window::new("settings").open(&mut self.settings).pivot(center).show(ctx, |ui| {
@@ -198,55 +282,39 @@ impl eframe::App for Lapis {
                         ui.label("zoom factor");
                         ui.add(DragValue::new(&mut self.zoom_factor).range(0.5..=4.).speed(0.1));
                     });
-                });
-            });
-            Window::new("sliders").open(&mut self.sliders_window).pivot(center).show(ctx, |ui| {
-                if ui.button("+").clicked() {
-                    self.sliders.push(SliderSettings {
-                        min: -1.,
-                        max: 1.,
-                        speed: 0.1,
-                        step_by: 0.,
-                        var: "".to_string(),
+                    ui.horizontal(|ui| {
+                        let mut limiter = self.protection.limiter.load(Ordering::Relaxed);
+                        ui.checkbox(&mut limiter, "limiter")
+                            .on_hover_text("limit the output to -1dB");
+                        self.protection.limiter.store(limiter, Ordering::Relaxed);
+                        let mut dc_block = self.protection.dc_block.load(Ordering::Relaxed);
+                        ui.checkbox(&mut dc_block, "dc block")
+                            .on_hover_text("remove dc offset from the output");
+                        self.protection.dc_block.store(dc_block, Ordering::Relaxed);
                     });
-                }
-                ScrollArea::vertical().show(ui, |ui| {
-                    for s in &mut self.sliders {
-                        ui.horizontal(|ui| {
-                            ui.add(
-                                TextEdit::singleline(&mut s.var)
-                                    .hint_text("variable")
-                                    .desired_width(80.),
-                            )
-                            .on_hover_text("the variable linked to this slider (float or shared)");
-                            let mut tmp = 0.;
-                            if let Some(v) = self.fmap.get(&s.var) {
-                                tmp = *v as f32;
-                            } else if let Some(v) = self.smap.get(&s.var) {
-                                tmp = v.value();
-                            }
-                            ui.add(
-                                Slider::new(&mut tmp, s.min..=s.max)
-                                    .step_by(s.step_by)
-                                    .drag_value_speed(s.speed),
-                            );
-                            ui.add(DragValue::new(&mut s.min).range(-1e6..=1e6))
-                                .on_hover_text("min");
-                            ui.add(DragValue::new(&mut s.max).range(-1e6..=1e6))
-                                .on_hover_text("max");
-                            ui.add(DragValue::new(&mut s.speed).range(0.0000001..=1.))
-                                .on_hover_text("speed when dragging the number");
-                            ui.add(DragValue::new(&mut s.step_by).range(0. ..=1.))
-                                .on_hover_text("step size when dragging the slider (0 to disable)");
-                            if let Some(v) = self.fmap.get_mut(&s.var) {
-                                *v = tmp as f64;
-                            } else if let Some(v) = self.smap.get(&s.var) {
-                                v.set(tmp);
-                            }
-                        });
-                    }
                 });
             });
+            let mut sliders_window = self.sliders_window;
+            Window::new("sliders").open(&mut sliders_window).pivot(center).show(ctx, |ui| {
+                self.sliders_ui(ui);
+            });
+            self.sliders_window = sliders_window;
+            let mut scope_window = self.scope_window;
+            Window::new("scope").open(&mut scope_window).pivot(center).show(ctx, |ui| {
+                self.scope_ui(ui);
+            });
+            self.scope_window = scope_window;
+            let mut spectrum_window = self.spectrum_window;
+            Window::new("spectrum").open(&mut spectrum_window).pivot(center).show(ctx, |ui| {
+                self.spectrum_ui(ui);
+            });
+            self.spectrum_window = spectrum_window;
+            let mut graph_window = self.graph_window;
+            Window::new("graph").open(&mut graph_window).pivot(center).show(ctx, |ui| {
+                self.graph_ui(ui);
+            });
+            self.graph_window = graph_window;
+            self.panels_ui(ctx);
             ScrollArea::vertical().stick_to_bottom(true).show(ui, |ui| {
                 ui.add(
                     TextEdit::multiline(&mut self.buffer)
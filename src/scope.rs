@@ -0,0 +1,82 @@
+use crate::eval::*;
+use eframe::egui::*;
+
+const COLORS: [Color32; 4] = [
+    Color32::from_rgb(0x5b, 0xce, 0xfa),
+    Color32::from_rgb(0xf5, 0xa9, 0xb8),
+    Color32::from_rgb(0xb4, 0xe0, 0x7a),
+    Color32::from_rgb(0xf0, 0xc6, 0x74),
+];
+
+impl Lapis {
+    pub fn scope_ui(&mut self, ui: &mut Ui) {
+        let sr = self.out_stream.as_ref().map_or(44100., |(c, _)| c.sample_rate.0 as f32);
+        let frames = ((self.scope.timebase / 1000. * sr) as usize).max(2);
+        // twice as much so there's room to find a trigger
+        let n = if self.scope.trigger { frames * 2 } else { frames };
+        let data = self.read_tap(&self.scope.source, n);
+        let names = self.tap_names();
+        let scope = &mut self.scope;
+        ui.horizontal(|ui| {
+            tap_combo(ui, "scope source", &mut scope.source, &names);
+            ui.selectable_value(&mut scope.trigger, true, "rising edge");
+            ui.selectable_value(&mut scope.trigger, false, "free run");
+            ui.add(DragValue::new(&mut scope.timebase).range(1. ..=300.).suffix(" ms"))
+                .on_hover_text("time across the scope");
+        });
+        while scope.colors.len() < data.len() {
+            scope.colors.push(COLORS[scope.colors.len() % COLORS.len()]);
+        }
+        ui.horizontal(|ui| {
+            for (i, color) in scope.colors.iter_mut().take(data.len()).enumerate() {
+                ui.label(i.to_string());
+                ui.color_edit_button_srgba(color);
+            }
+        });
+        let size = ui.available_size().max(vec2(100., 60.));
+        let (response, painter) = ui.allocate_painter(size, Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0., ui.visuals().extreme_bg_color);
+        painter.hline(rect.x_range(), rect.center().y, (1., ui.visuals().weak_text_color()));
+        let len = data.first().map_or(0, |d| d.len());
+        if len >= 2 {
+            let frames = frames.min(len);
+            // the latest rising edge that still has a full screen after it
+            let start = if scope.trigger {
+                let d = &data[0];
+                (1..=len - frames)
+                    .rev()
+                    .find(|&i| d[i - 1] < 0. && d[i] >= 0.)
+                    .unwrap_or(len - frames)
+            } else {
+                len - frames
+            };
+            // no more than a couple of points per pixel
+            let step = (frames / (rect.width() as usize * 2).max(1)).max(1);
+            for (samples, color) in data.iter().zip(&scope.colors) {
+                let points: Vec<Pos2> = (start..start + frames)
+                    .step_by(step)
+                    .map(|i| {
+                        let x =
+                            rect.left() + (i - start) as f32 / (frames - 1) as f32 * rect.width();
+                        let y = rect.center().y - samples[i].clamp(-1., 1.) * rect.height() / 2.;
+                        pos2(x, y)
+                    })
+                    .collect();
+                painter.add(Shape::line(points, Stroke::new(1., *color)));
+            }
+        }
+        ui.ctx().request_repaint();
+    }
+}
+
+// pick the output or one of the snoops
+pub fn tap_combo(ui: &mut Ui, id: &str, source: &mut String, names: &[String]) {
+    let selected = if source.is_empty() { "output" } else { source.as_str() };
+    ComboBox::from_id_salt(id).selected_text(selected.to_string()).show_ui(ui, |ui| {
+        ui.selectable_value(source, String::new(), "output");
+        for name in names {
+            ui.selectable_value(source, name.clone(), name);
+        }
+    });
+}
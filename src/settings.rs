@@ -0,0 +1,39 @@
+use crate::eval::*;
+use eframe::egui::{Key, Modifiers};
+use serde::{Deserialize, Serialize};
+
+const KEY: &str = "lapis";
+
+// what's kept between sessions (the code theme is kept by egui itself)
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct Saved {
+    sliders: Vec<SliderSettings>,
+    widgets: Vec<Widget>,
+    panels: Vec<Panel>,
+    keys: Vec<((Modifiers, Key, bool), String)>,
+    zoom_factor: Option<f32>,
+}
+
+impl Lapis {
+    pub fn load_settings(&mut self, storage: &dyn eframe::Storage) {
+        let Some(saved) = eframe::get_value::<Saved>(storage, KEY) else { return };
+        self.sliders = saved.sliders;
+        self.widgets = saved.widgets;
+        self.panels = saved.panels;
+        self.keys = saved.keys.into_iter().collect();
+        if let Some(zoom) = saved.zoom_factor {
+            self.zoom_factor = zoom;
+        }
+    }
+    pub fn save_settings(&self, storage: &mut dyn eframe::Storage) {
+        let saved = Saved {
+            sliders: self.sliders.clone(),
+            widgets: self.widgets.clone(),
+            panels: self.panels.clone(),
+            keys: self.keys.iter().map(|(k, v)| (*k, v.clone())).collect(),
+            zoom_factor: Some(self.zoom_factor),
+        };
+        eframe::set_value(storage, KEY, &saved);
+    }
+}
@@ -0,0 +1,218 @@
+use crate::eval::*;
+use crate::widgets::{get, set};
+use eframe::egui::*;
+
+impl Curve {
+    // the value at a position (0..1) along a slider
+    pub fn value(&self, t: f32, min: f32, max: f32) -> f32 {
+        match self {
+            Curve::Log if min > 0. && max > 0. => min * (max / min).powf(t),
+            Curve::Power(p) => min + (max - min) * t.powf(*p),
+            _ => min + (max - min) * t,
+        }
+    }
+    // where a value is along a slider
+    pub fn position(&self, v: f32, min: f32, max: f32) -> f32 {
+        if max == min {
+            return 0.;
+        }
+        let t = match self {
+            Curve::Log if min > 0. && max > 0. && v > 0. => (v / min).ln() / (max / min).ln(),
+            Curve::Power(p) => ((v - min) / (max - min)).max(0.).powf(1. / p),
+            _ => (v - min) / (max - min),
+        };
+        t.clamp(0., 1.)
+    }
+    fn name(&self) -> String {
+        match self {
+            Curve::Linear => "linear".into(),
+            Curve::Log => "log".into(),
+            Curve::Power(p) => format!("power {p}"),
+        }
+    }
+}
+
+impl Automation {
+    // the recorded value at a time (each value holds until the next one)
+    fn value(&self, time: f64) -> Option<f32> {
+        let i = self.points.partition_point(|p| p.0 <= time);
+        self.points.get(i.saturating_sub(1)).map(|p| p.1)
+    }
+}
+
+impl Lapis {
+    // midi and automation move the sliders even when the window is closed
+    pub fn update_sliders(&mut self, ctx: &Context) {
+        let now = ctx.input(|i| i.time);
+        #[cfg(not(target_arch = "wasm32"))]
+        for (channel, cc, value) in self.midi.cc_events() {
+            if let Some(s) = self.slider_learn.take().and_then(|i| self.sliders.get_mut(i)) {
+                s.cc = Some((channel, cc));
+            }
+            let t = value as f32 / 127.;
+            let moved: Vec<(String, f32)> = self
+                .sliders
+                .iter()
+                .filter(|s| s.cc == Some((channel, cc)))
+                .map(|s| (s.var.clone(), s.curve.value(t, s.min, s.max)))
+                .collect();
+            for (var, v) in moved {
+                set(self, &var, v);
+            }
+        }
+        let mut sliders = std::mem::take(&mut self.sliders);
+        for s in &mut sliders {
+            let a = &mut s.automation;
+            if let Some(start) = a.recording {
+                // only the changes are kept
+                if let Some(v) = get(self, &s.var)
+                    && a.points.last().is_none_or(|p| p.1 != v)
+                {
+                    a.points.push((now - start, v));
+                }
+                ctx.request_repaint();
+            } else if a.playing && a.length > 0. {
+                #[cfg(not(target_arch = "wasm32"))]
+                let restart = a.start.is_none();
+                let start = *a.start.get_or_insert(now);
+                // a shared keeps looping when the window isn't repainting
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Some(shared) = self.smap.get(&s.var) {
+                    if restart || !self.slider_players.contains_key(&s.var) {
+                        let (shared, a) = (shared.clone(), a.clone());
+                        let begin = std::time::Instant::now();
+                        let player = run_timer(0.001, move || {
+                            let time = begin.elapsed().as_secs_f64() % a.length;
+                            if let Some(v) = a.value(time) {
+                                shared.set(v);
+                            }
+                        });
+                        self.slider_players.insert(s.var.clone(), player);
+                    }
+                    continue;
+                }
+                if let Some(v) = a.value((now - start) % a.length) {
+                    set(self, &s.var, v);
+                }
+                ctx.request_repaint();
+            }
+        }
+        // stop the players of loops that were turned off or removed
+        #[cfg(not(target_arch = "wasm32"))]
+        self.slider_players.retain(|var, _| {
+            let playing = sliders.iter().any(|s| &s.var == var && s.automation.playing);
+            playing && self.smap.contains_key(var)
+        });
+        self.sliders = sliders;
+    }
+    pub fn sliders_ui(&mut self, ui: &mut Ui) {
+        if ui.button("+").clicked() {
+            self.sliders.push(SliderSettings {
+                min: -1.,
+                max: 1.,
+                speed: 0.1,
+                ..Default::default()
+            });
+        }
+        let now = ui.input(|i| i.time);
+        ScrollArea::vertical().show(ui, |ui| {
+            let mut sliders = std::mem::take(&mut self.sliders);
+            for (i, s) in sliders.iter_mut().enumerate() {
+                ui.horizontal(|ui| self.slider_ui(ui, i, s, now));
+            }
+            self.sliders = sliders;
+            self.widgets_ui(ui);
+        });
+    }
+    fn slider_ui(&mut self, ui: &mut Ui, i: usize, s: &mut SliderSettings, now: f64) {
+        ui.add(TextEdit::singleline(&mut s.var).hint_text("variable").desired_width(80.))
+            .on_hover_text("the variable linked to this slider (float or shared)");
+        let mut tmp = get(self, &s.var).unwrap_or(0.);
+        let (min, max, curve) = (s.min, s.max, s.curve);
+        let changed = if curve == Curve::Linear {
+            ui.add(
+                Slider::new(&mut tmp, min..=max)
+                    .step_by(s.step_by)
+                    .drag_value_speed(s.speed)
+                    .suffix(&s.unit),
+            )
+            .changed()
+        } else {
+            // the slider moves along the curve, the number shows the value
+            let mut t = curve.position(tmp, min, max);
+            let changed = ui
+                .add(
+                    Slider::new(&mut t, 0. ..=1.)
+                        .custom_formatter(|t, _| format!("{:.2}", curve.value(t as f32, min, max)))
+                        .custom_parser(|text| {
+                            let v = text.trim().parse::<f32>().ok()?;
+                            Some(curve.position(v, min, max) as f64)
+                        })
+                        .suffix(&s.unit),
+                )
+                .changed();
+            tmp = curve.value(t, min, max);
+            changed
+        };
+        if changed {
+            set(self, &s.var, tmp);
+        }
+        ui.add(DragValue::new(&mut s.min).range(-1e6..=1e6)).on_hover_text("min");
+        ui.add(DragValue::new(&mut s.max).range(-1e6..=1e6)).on_hover_text("max");
+        ui.add(DragValue::new(&mut s.speed).range(0.0000001..=1.))
+            .on_hover_text("speed when dragging the number");
+        ui.add(DragValue::new(&mut s.step_by).range(0. ..=1.))
+            .on_hover_text("step size when dragging the slider (0 to disable)");
+        ComboBox::from_id_salt(("curve", i)).width(70.).selected_text(s.curve.name()).show_ui(
+            ui,
+            |ui| {
+                ui.selectable_value(&mut s.curve, Curve::Linear, "linear");
+                ui.selectable_value(&mut s.curve, Curve::Log, "log");
+                ui.selectable_value(&mut s.curve, Curve::Power(3.), "power");
+            },
+        );
+        if let Curve::Power(p) = &mut s.curve {
+            ui.add(DragValue::new(p).range(0.1..=10.).speed(0.05)).on_hover_text("power");
+        }
+        ui.add(TextEdit::singleline(&mut s.unit).hint_text("unit").desired_width(30.));
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let learning = self.slider_learn == Some(i);
+            let hover = match s.cc {
+                Some((channel, cc)) => {
+                    format!("cc {cc} on channel {channel}. right click to unbind")
+                }
+                None => "click then move a midi controller".into(),
+            };
+            let text = if learning { "..." } else { "midi" };
+            let midi = ui.selectable_label(learning || s.cc.is_some(), text).on_hover_text(hover);
+            if midi.clicked() {
+                self.slider_learn = if learning { None } else { Some(i) };
+            }
+            if midi.secondary_clicked() {
+                s.cc = None;
+            }
+        }
+        let a = &mut s.automation;
+        let rec = ui
+            .selectable_label(a.recording.is_some(), "rec")
+            .on_hover_text("record the movements of this slider");
+        if rec.clicked() {
+            if let Some(start) = a.recording.take() {
+                a.length = now - start;
+            } else {
+                a.points.clear();
+                a.playing = false;
+                a.recording = Some(now);
+            }
+        }
+        let can_play = !a.points.is_empty() && a.recording.is_none();
+        let play = ui
+            .add_enabled(can_play, Button::selectable(a.playing, "loop"))
+            .on_hover_text(format!("loop the recording ({:.1}s)", a.length));
+        if play.clicked() {
+            a.playing = !a.playing;
+            a.start = None;
+        }
+    }
+}
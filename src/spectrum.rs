@@ -0,0 +1,222 @@
+use crate::eval::*;
+use crate::scope::tap_combo;
+use eframe::egui::*;
+use rustfft::{FftPlanner, num_complex::Complex};
+use std::sync::{Arc, Mutex};
+
+const MIN_DB: f32 = -100.;
+const MIN_HZ: f32 = 20.;
+// size of the spectrogram image (time, frequency)
+const COLUMNS: usize = 400;
+const ROWS: usize = 256;
+
+// frames of the tap between analyses (and spectrogram columns), so the time axis and
+// the averaging don't depend on how often the window is drawn
+const HOP: usize = 1024;
+
+// what the window keeps between frames. it lives in egui's memory, not in `Lapis`
+struct Analyzer {
+    planner: FftPlanner<f32>,
+    // in db, for each bin
+    levels: Vec<f32>,
+    peaks: Vec<f32>,
+    image: ColorImage,
+    texture: Option<TextureHandle>,
+    // the tap, and how far into it the analyses got
+    source: String,
+    analyzed: usize,
+}
+
+impl Analyzer {
+    fn new() -> Self {
+        Analyzer {
+            planner: FftPlanner::new(),
+            levels: Vec::new(),
+            peaks: Vec::new(),
+            image: ColorImage::filled([COLUMNS, ROWS], Color32::BLACK),
+            texture: None,
+            source: String::new(),
+            analyzed: 0,
+        }
+    }
+    // fft of the `n` frames before `end` (channels mixed), averaged into the levels
+    fn analyze(&mut self, data: &[Vec<f32>], end: usize, n: usize, averaging: f32) {
+        let mut buffer: Vec<Complex<f32>> = (0..n)
+            .map(|i| {
+                let s = data.iter().map(|ch| ch[end - n + i]).sum::<f32>() / data.len() as f32;
+                // hann window
+                let w = 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / n as f32).cos();
+                Complex::new(s * w, 0.)
+            })
+            .collect();
+        self.planner.plan_fft_forward(n).process(&mut buffer);
+        let bins = n / 2;
+        if self.levels.len() != bins {
+            self.levels = vec![MIN_DB; bins];
+            self.peaks = vec![MIN_DB; bins];
+        }
+        // a full scale sine is 0db (the window sums to n/2, and half goes to the negative bin)
+        let scale = 4. / n as f32;
+        for (i, c) in buffer.iter().take(bins).enumerate() {
+            let db = (20. * (c.norm() * scale).log10()).max(MIN_DB);
+            let level = &mut self.levels[i];
+            *level = *level * averaging + db * (1. - averaging);
+            self.peaks[i] = self.peaks[i].max(*level);
+        }
+    }
+    // the loudest bin between two frequencies
+    fn level(values: &[f32], lo: f32, hi: f32, hz_per_bin: f32) -> f32 {
+        let last = values.len().saturating_sub(1);
+        let lo = ((lo / hz_per_bin) as usize).min(last);
+        let hi = ((hi / hz_per_bin) as usize).clamp(lo, last);
+        values[lo..=hi].iter().copied().fold(MIN_DB, f32::max)
+    }
+    // frequency at x (0..1) on a log scale from MIN_HZ to nyquist
+    fn freq(x: f32, sr: f32) -> f32 {
+        MIN_HZ * (sr / 2. / MIN_HZ).powf(x)
+    }
+    fn x(freq: f32, sr: f32) -> f32 {
+        (freq / MIN_HZ).ln() / (sr / 2. / MIN_HZ).ln()
+    }
+    fn draw_spectrum(&self, ui: &mut Ui, rect: Rect, sr: f32, size: usize, peak_hold: bool) {
+        let painter = ui.painter_at(rect);
+        let grid = ui.visuals().weak_text_color();
+        let font = FontId::monospace(10.);
+        for f in [100., 1000., 10000.] {
+            let x = rect.left() + Self::x(f, sr) * rect.width();
+            painter.vline(x, rect.y_range(), (1., grid.gamma_multiply(0.3)));
+            let label = if f < 1000. { format!("{}", f) } else { format!("{}k", f / 1000.) };
+            painter.text(pos2(x + 2., rect.top()), Align2::LEFT_TOP, label, font.clone(), grid);
+        }
+        for db in (MIN_DB as i32..0).step_by(20) {
+            let y = rect.top() + db as f32 / MIN_DB * rect.height();
+            painter.hline(rect.x_range(), y, (1., grid.gamma_multiply(0.3)));
+            painter.text(pos2(rect.left() + 2., y), Align2::LEFT_BOTTOM, db, font.clone(), grid);
+        }
+        if self.levels.is_empty() {
+            return;
+        }
+        let hz_per_bin = sr / size as f32;
+        let columns = rect.width().max(1.) as usize;
+        let line = |values: &[f32]| -> Vec<Pos2> {
+            (0..columns)
+                .map(|c| {
+                    let lo = Self::freq(c as f32 / columns as f32, sr);
+                    let hi = Self::freq((c + 1) as f32 / columns as f32, sr);
+                    let db = Self::level(values, lo, hi, hz_per_bin);
+                    pos2(rect.left() + c as f32, rect.top() + db / MIN_DB * rect.height())
+                })
+                .collect()
+        };
+        if peak_hold {
+            painter.add(Shape::line(line(&self.peaks), (1., grid)));
+        }
+        painter.add(Shape::line(line(&self.levels), (1., ui.visuals().strong_text_color())));
+    }
+    // scroll the spectrogram one column, to the current levels
+    fn push_column(&mut self, sr: f32, size: usize) {
+        let hz_per_bin = sr / size as f32;
+        for row in 0..ROWS {
+            // low frequencies at the bottom
+            let x = 1. - row as f32 / ROWS as f32;
+            let lo = Self::freq(x - 1. / ROWS as f32, sr);
+            let hi = Self::freq(x, sr);
+            let db = Self::level(&self.levels, lo, hi, hz_per_bin);
+            let pixels = &mut self.image.pixels[row * COLUMNS..(row + 1) * COLUMNS];
+            pixels.rotate_left(1);
+            pixels[COLUMNS - 1] = heat(1. - db / MIN_DB);
+        }
+    }
+    fn draw_spectrogram(&mut self, ui: &mut Ui, rect: Rect, new_column: bool) {
+        let texture = self.texture.get_or_insert_with(|| {
+            ui.ctx().load_texture("spectrogram", self.image.clone(), TextureOptions::LINEAR)
+        });
+        if new_column {
+            texture.set(self.image.clone(), TextureOptions::LINEAR);
+        }
+        let uv = Rect::from_min_max(pos2(0., 0.), pos2(1., 1.));
+        ui.painter_at(rect).image(texture.id(), rect, uv, Color32::WHITE);
+    }
+}
+
+// black -> blue -> pink -> white
+fn heat(t: f32) -> Color32 {
+    let stops = [(0., 0., 0.), (0.1, 0.2, 0.6), (0.95, 0.4, 0.6), (1., 1., 1.)];
+    let t = t.clamp(0., 1.) * (stops.len() - 1) as f32;
+    let i = (t as usize).min(stops.len() - 2);
+    let f = t - i as f32;
+    let (a, b) = (stops[i], stops[i + 1]);
+    let mix = |a: f32, b: f32| ((a + (b - a) * f) * 255.) as u8;
+    Color32::from_rgb(mix(a.0, b.0), mix(a.1, b.1), mix(a.2, b.2))
+}
+
+impl Lapis {
+    pub fn spectrum_ui(&mut self, ui: &mut Ui) {
+        let sr = self.out_stream.as_ref().map_or(44100., |(c, _)| c.sample_rate.0 as f32);
+        let analyzer = ui.data_mut(|d| {
+            let new = || Arc::new(Mutex::new(Analyzer::new()));
+            d.get_temp_mut_or_insert_with(Id::new("spectrum analyzer"), new).clone()
+        });
+        let Ok(mut analyzer) = analyzer.lock() else { return };
+        let names = self.tap_names();
+        let spectrum = &mut self.spectrum;
+        ui.horizontal(|ui| {
+            tap_combo(ui, "spectrum source", &mut spectrum.source, &names);
+            ComboBox::from_id_salt("fft size")
+                .selected_text(spectrum.size.to_string())
+                .show_ui(ui, |ui| {
+                    for size in [1024, 2048, 4096, 8192, 16384] {
+                        ui.selectable_value(&mut spectrum.size, size, size.to_string());
+                    }
+                })
+                .response
+                .on_hover_text("fft size");
+            ui.add(DragValue::new(&mut spectrum.averaging).range(0. ..=0.99).speed(0.01))
+                .on_hover_text("averaging");
+            ui.toggle_value(&mut spectrum.peak_hold, "peak hold");
+            if ui.button("reset").on_hover_text("reset the peaks").clicked() {
+                analyzer.peaks.fill(MIN_DB);
+            }
+            ui.toggle_value(&mut spectrum.spectrogram, "spectrogram");
+        });
+        let spectrum = &self.spectrum;
+        let size = spectrum.size;
+        // one analysis for every HOP frames written since the last one
+        let written = self.tap_written(&spectrum.source);
+        if analyzer.source != spectrum.source || written < analyzer.analyzed {
+            analyzer.source = spectrum.source.clone();
+            analyzer.analyzed = written.saturating_sub(HOP);
+        }
+        // after a long pause only the last screenful is caught up on
+        let mut steps = (written - analyzer.analyzed) / HOP;
+        if steps > COLUMNS {
+            analyzer.analyzed = written - COLUMNS * HOP;
+            steps = COLUMNS;
+        }
+        let (end, data) = self.read_tap_counted(&spectrum.source, size + steps * HOP);
+        let len = data.first().map_or(0, |d| d.len());
+        let mut new_column = false;
+        for _ in 0..steps {
+            analyzer.analyzed += HOP;
+            // where this analysis ends in `data`. skipped if the tap doesn't have enough before it
+            let at = analyzer.analyzed;
+            if at <= end && at + len >= end + size {
+                analyzer.analyze(&data, len - (end - at), size, spectrum.averaging);
+                if spectrum.spectrogram {
+                    analyzer.push_column(sr, size);
+                    new_column = true;
+                }
+            }
+        }
+        let rect_size = ui.available_size().max(vec2(100., 60.));
+        let (response, painter) = ui.allocate_painter(rect_size, Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0., ui.visuals().extreme_bg_color);
+        if spectrum.spectrogram {
+            analyzer.draw_spectrogram(ui, rect, new_column);
+        } else {
+            analyzer.draw_spectrum(ui, rect, sr, size, spectrum.peak_hold);
+        }
+        ui.ctx().request_repaint();
+    }
+}
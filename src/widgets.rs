@@ -0,0 +1,189 @@
+use crate::eval::*;
+use eframe::egui::*;
+
+const KNOB: f32 = 40.;
+const PAD: f32 = 120.;
+const MULTI: Vec2 = vec2(200., 80.);
+
+// the value of a float or shared
+pub fn get(lapis: &Lapis, var: &str) -> Option<f32> {
+    lapis.fmap.get(var).map(|v| *v as f32).or_else(|| lapis.smap.get(var).map(|s| s.value()))
+}
+
+pub fn set(lapis: &mut Lapis, var: &str, value: f32) {
+    if let Some(v) = lapis.fmap.get_mut(var) {
+        *v = value as f64;
+    } else if let Some(s) = lapis.smap.get(var) {
+        s.set(value);
+    }
+}
+
+// 0..1 position of a value in a range
+fn norm(v: f32, min: f32, max: f32) -> f32 {
+    if max == min { 0. } else { ((v - min) / (max - min)).clamp(0., 1.) }
+}
+
+fn knob(ui: &mut Ui, value: &mut f32, min: f32, max: f32) -> Response {
+    let (rect, mut response) = ui.allocate_exact_size(vec2(KNOB, KNOB), Sense::drag());
+    // a full turn of the knob is 200 pixels of dragging
+    let delta = response.drag_delta().y;
+    if delta != 0. {
+        *value -= delta * (max - min) / 200.;
+        *value = value.clamp(min.min(max), max.max(min));
+        response.mark_changed();
+    }
+    let painter = ui.painter_at(rect);
+    let visuals = ui.style().interact(&response);
+    let radius = KNOB / 2. - 2.;
+    painter.circle(rect.center(), radius, visuals.bg_fill, visuals.fg_stroke);
+    // -135 to 135 degrees from the top
+    let angle = (norm(*value, min, max) * 1.5 - 0.75) * std::f32::consts::PI;
+    let tip = rect.center() + vec2(angle.sin(), -angle.cos()) * radius;
+    painter.line_segment([rect.center(), tip], visuals.fg_stroke);
+    response
+}
+
+fn xy_pad(ui: &mut Ui, x: &mut f32, y: &mut f32, min: f32, max: f32) -> Response {
+    let (rect, mut response) = ui.allocate_exact_size(vec2(PAD, PAD), Sense::click_and_drag());
+    if let Some(pos) = response.interact_pointer_pos() {
+        let p = (pos - rect.min) / rect.size();
+        *x = min + p.x.clamp(0., 1.) * (max - min);
+        *y = min + (1. - p.y.clamp(0., 1.)) * (max - min);
+        response.mark_changed();
+    }
+    let painter = ui.painter_at(rect);
+    let visuals = ui.visuals();
+    painter.rect_filled(rect, 2., visuals.extreme_bg_color);
+    let dot = rect.min + vec2(norm(*x, min, max), 1. - norm(*y, min, max)) * rect.size();
+    painter.hline(rect.x_range(), dot.y, (1., visuals.weak_text_color()));
+    painter.vline(dot.x, rect.y_range(), (1., visuals.weak_text_color()));
+    painter.circle_filled(dot, 4., visuals.strong_text_color());
+    response
+}
+
+fn multi_slider(ui: &mut Ui, values: &mut [f32], min: f32, max: f32) -> Response {
+    let (rect, response) = ui.allocate_exact_size(MULTI, Sense::click_and_drag());
+    let n = values.len().max(1);
+    let width = rect.width() / n as f32;
+    if let Some(pos) = response.interact_pointer_pos() {
+        let i = (((pos.x - rect.left()) / width) as usize).min(n - 1);
+        let p = 1. - ((pos.y - rect.top()) / rect.height()).clamp(0., 1.);
+        if let Some(v) = values.get_mut(i) {
+            *v = min + p * (max - min);
+        }
+    }
+    let painter = ui.painter_at(rect);
+    let visuals = ui.visuals();
+    painter.rect_filled(rect, 2., visuals.extreme_bg_color);
+    for (i, v) in values.iter().enumerate() {
+        let top = rect.bottom() - norm(*v, min, max) * rect.height();
+        let x = rect.left() + i as f32 * width;
+        let bar = Rect::from_min_max(pos2(x + 1., top), pos2(x + width - 1., rect.bottom()));
+        painter.rect_filled(bar, 0., visuals.selection.bg_fill);
+    }
+    response
+}
+
+impl Lapis {
+    // show a list of controls, and return the code of the buttons that were pressed
+    fn show_widgets(
+        &mut self,
+        ui: &mut Ui,
+        widgets: &mut Vec<Widget>,
+        removable: bool,
+    ) -> Vec<String> {
+        let mut remove = None;
+        let mut pressed = Vec::new();
+        for (i, widget) in widgets.iter().enumerate() {
+            ui.horizontal(|ui| {
+                match widget {
+                    Widget::Knob { var, min, max } => {
+                        if let Some(mut v) = get(self, var) {
+                            // only written when moved, so values set elsewhere aren't overwritten
+                            let response = knob(ui, &mut v, *min, *max);
+                            if response.on_hover_text(v.to_string()).changed() {
+                                set(self, var, v);
+                            }
+                        }
+                        ui.label(var);
+                    }
+                    Widget::Toggle { var } => {
+                        if let Some(b) = self.bmap.get_mut(var) {
+                            ui.toggle_value(b, var.as_str());
+                        } else {
+                            ui.label(var);
+                        }
+                    }
+                    Widget::Button { label, code } => {
+                        if ui.button(label).on_hover_text(code).clicked() {
+                            pressed.push(code.clone());
+                        }
+                    }
+                    Widget::Xy { x, y, min, max } => {
+                        if let (Some(sx), Some(sy)) = (self.smap.get(x), self.smap.get(y)) {
+                            let (mut vx, mut vy) = (sx.value(), sy.value());
+                            let response = xy_pad(ui, &mut vx, &mut vy, *min, *max);
+                            if response.on_hover_text(format!("{vx}, {vy}")).changed() {
+                                sx.set(vx);
+                                sy.set(vy);
+                            }
+                        }
+                        ui.label(format!("{x}, {y}"));
+                    }
+                    Widget::Multi { var, min, max } => {
+                        if let Some(v) = self.vmap.get_mut(var) {
+                            multi_slider(ui, v, *min, *max);
+                        }
+                        ui.label(var);
+                    }
+                    Widget::Slider { var, min, max } => {
+                        if let Some(mut v) = get(self, var) {
+                            if ui.add(Slider::new(&mut v, *min..=*max).text(var)).changed() {
+                                set(self, var, v);
+                            }
+                        } else {
+                            ui.label(var);
+                        }
+                    }
+                    Widget::Label { text } => {
+                        ui.label(text);
+                    }
+                }
+                if removable && ui.small_button("x").on_hover_text("remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            widgets.remove(i);
+        }
+        pressed
+    }
+    fn run_pressed(&mut self, pressed: Vec<String>) {
+        for code in pressed {
+            if self.quiet {
+                self.quiet_eval(&code);
+            } else {
+                self.eval(&code);
+            }
+        }
+    }
+    pub fn widgets_ui(&mut self, ui: &mut Ui) {
+        // taken out so the widgets can change the variables
+        let mut widgets = std::mem::take(&mut self.widgets);
+        let pressed = self.show_widgets(ui, &mut widgets, true);
+        self.widgets = widgets;
+        self.run_pressed(pressed);
+    }
+    pub fn panels_ui(&mut self, ctx: &Context) {
+        let mut panels = std::mem::take(&mut self.panels);
+        let mut pressed = Vec::new();
+        for panel in &mut panels {
+            Window::new(&panel.name).open(&mut panel.open).show(ctx, |ui| {
+                pressed.extend(self.show_widgets(ui, &mut panel.widgets, false));
+            });
+        }
+        self.panels = panels;
+        self.run_pressed(pressed);
+    }
+}